@@ -0,0 +1,29 @@
+//! Records the revision of the SimplicityHL compiler (`simfony`) resolved in Cargo.lock,
+//! which build artifacts carry so that `simply verify` can tell compiler changes apart.
+
+use std::fs;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    let lock = fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let revision = compiler_revision(&lock).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=SIMFONY_REVISION={}", revision);
+}
+
+/// Commit of the `simfony` git dependency, or its version if it comes from a registry.
+fn compiler_revision(lock: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|package| package.lines().any(|line| line == "name = \"simfony\""))?;
+    let field = |name: &str| {
+        package.lines().find_map(|line| {
+            line.strip_prefix(name)
+                .and_then(|value| value.strip_prefix(" = "))
+                .map(|value| value.trim_matches('"').to_string())
+        })
+    };
+    match field("source")?.split_once('#') {
+        Some((_, commit)) => Some(commit.to_string()),
+        None => field("version"),
+    }
+}
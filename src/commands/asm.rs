@@ -0,0 +1,241 @@
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use clap::Args;
+use simplicity::human_encoding::Forest;
+use simplicity::jet::Elements;
+use simplicity::{RedeemNode, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::commands::build::{
+    format_node_bounds, load_artifacts_from, program_to_assembly, write_build_output,
+    BuildArtifacts,
+};
+
+#[derive(Args, Debug)]
+pub struct AsmArgs {
+    /// Path to the Simplicity assembly file (`.simp`)
+    #[arg(long)]
+    pub input: PathBuf,
+
+    /// Path to a JSON file mapping witness node names to hex values, e.g. `{"sig": "0x..."}`
+    #[arg(long)]
+    pub witness: Option<PathBuf>,
+
+    /// Output directory for the assembled program (will use `target` by default)
+    #[arg(long, name = "target-dir", default_value = "./target")]
+    pub target_dir: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct DisasmArgs {
+    /// Program to disassemble, encoded as given by `--encoding`
+    #[arg(
+        long,
+        conflicts_with = "artifact",
+        required_unless_present = "artifact"
+    )]
+    pub program: Option<String>,
+
+    /// Encoding of `--program`
+    #[arg(long, value_enum, default_value = "base64", requires = "program")]
+    pub encoding: ProgramEncoding,
+
+    /// Path to build artifacts (JSON) to disassemble
+    #[arg(long)]
+    pub artifact: Option<PathBuf>,
+
+    /// Write the assembly to a file instead of printing it
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Encoding of a program given on the command line
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ProgramEncoding {
+    /// Base64, as in build artifacts and the Simplicity web IDE
+    #[clap(name = "base64")]
+    Base64,
+    /// Hex, as in the witness stack of a transaction (an `0x` prefix is allowed)
+    #[clap(name = "hex")]
+    Hex,
+}
+
+/// Load witness values for an assembly program.
+///
+/// Values are hex-encoded and must have a power-of-two byte length (`u8` .. `u256`, signatures).
+fn load_asm_witness(path: Option<&PathBuf>) -> Result<HashMap<Arc<str>, Value>> {
+    let Some(path) = path else {
+        return Ok(HashMap::new());
+    };
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read witness file: {}", path.display()))?;
+    let entries: HashMap<String, String> =
+        serde_json::from_str(&content).with_context(|| "Failed to parse witness")?;
+
+    entries
+        .into_iter()
+        .map(|(name, value)| {
+            let bytes = hex::decode(value.trim_start_matches("0x"))
+                .with_context(|| format!("Witness `{}` is not valid hex", name))?;
+            anyhow::ensure!(
+                bytes.len().is_power_of_two(),
+                "Witness `{}` must have a power-of-two byte length, got {} bytes",
+                name,
+                bytes.len()
+            );
+            Ok((Arc::from(name.as_str()), bytes_value(&bytes)))
+        })
+        .collect()
+}
+
+/// Value of type `2^(8n)` holding the bytes in big-endian order, `bytes` has a power-of-two length.
+fn bytes_value(bytes: &[u8]) -> Value {
+    match bytes {
+        [byte] => Value::u8(*byte),
+        _ => {
+            let (left, right) = bytes.split_at(bytes.len() / 2);
+            Value::product(bytes_value(left), bytes_value(right))
+        }
+    }
+}
+
+/// Decode a program in the given encoding.
+fn decode_program(program: &str, encoding: ProgramEncoding) -> Result<Vec<u8>> {
+    let program = program.trim();
+    match encoding {
+        ProgramEncoding::Base64 => base64::engine::general_purpose::STANDARD
+            .decode(program)
+            .with_context(|| "Program is not valid base64, pass `--encoding hex` for hex"),
+        ProgramEncoding::Hex => hex::decode(program.trim_start_matches("0x"))
+            .with_context(|| "Program is not valid hex"),
+    }
+}
+
+/// Parse a program in Simplicity assembly and attach the witness values.
+fn assemble(text: &str, witness: &HashMap<Arc<str>, Value>) -> Result<Arc<RedeemNode<Elements>>> {
+    let forest =
+        Forest::<Elements>::parse(text).map_err(|e| anyhow!("Failed to parse assembly:\n{}", e))?;
+    let witness_node = forest
+        .to_witness_node(witness)
+        .ok_or_else(|| anyhow!("Assembly does not define `main`"))?;
+
+    witness_node.finalize_unpruned().map_err(|e| {
+        anyhow!(
+            "Failed to finalize program (missing witness values?): {}",
+            e
+        )
+    })
+}
+
+pub fn asm(args: AsmArgs) -> Result<()> {
+    let text = fs::read_to_string(&args.input)
+        .with_context(|| format!("Failed to read assembly file: {}", args.input.display()))?;
+    let witness = load_asm_witness(args.witness.as_ref())?;
+    let node = assemble(&text, &witness)?;
+    println!("CMR: {}", node.cmr());
+    println!("{}", format_node_bounds(&node.bounds()));
+
+    let (program_bytes, witness_bytes) = node.encode_to_vec();
+    let artifacts = BuildArtifacts {
+        program: program_bytes,
+        witness: args.witness.as_ref().map(|_| witness_bytes),
+        metadata: None,
+    };
+
+    let program_name = args
+        .input
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow!("Invalid input file name: {}", args.input.display()))?;
+    write_build_output(&args.target_dir, program_name, artifacts, false)
+}
+
+pub fn disasm(args: DisasmArgs) -> Result<()> {
+    let program = match (&args.program, &args.artifact) {
+        (Some(program), _) => decode_program(program, args.encoding)?,
+        (None, Some(path)) => load_artifacts_from(path)?.program,
+        (None, None) => anyhow::bail!("Either --program or --artifact must be provided"),
+    };
+
+    let (cmr, assembly) = program_to_assembly(&program)?;
+    println!("CMR: {}", cmr);
+
+    if let Some(output) = args.output {
+        fs::write(&output, assembly)
+            .with_context(|| format!("Failed to write assembly file: {}", output.display()))?;
+        println!("Assembly written to: {}", output.display());
+    } else {
+        println!("{}", assembly);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simfony::{Arguments, CompiledProgram};
+    use simplicity::Cmr;
+
+    const SOURCE: &str = r#"fn main() {
+    let x: u32 = witness::X;
+    assert!(jet::le_32(1000, x));
+    jet::check_lock_height(1000);
+}
+"#;
+
+    /// Encoded program of `SOURCE`, without witness data.
+    fn encoded_program() -> (Cmr, Vec<u8>) {
+        let compiled = CompiledProgram::new(SOURCE, Arguments::default(), false).unwrap();
+        let commit = compiled.commit();
+        (commit.cmr(), commit.encode_to_vec())
+    }
+
+    #[test]
+    fn test_decode_program() {
+        let (_, program) = encoded_program();
+        let base64 = base64::engine::general_purpose::STANDARD.encode(&program);
+        let hex = hex::encode(&program);
+
+        assert_eq!(
+            decode_program(&base64, ProgramEncoding::Base64).unwrap(),
+            program
+        );
+        assert_eq!(decode_program(&hex, ProgramEncoding::Hex).unwrap(), program);
+        assert_eq!(
+            decode_program(&format!(" 0x{}\n", hex), ProgramEncoding::Hex).unwrap(),
+            program
+        );
+        // Valid hex is also valid base64, the encoding is not guessed
+        assert_eq!(
+            decode_program("deadbeef", ProgramEncoding::Base64).unwrap(),
+            [0x75, 0xe6, 0x9d, 0x6d, 0xe7, 0x9f]
+        );
+        assert!(decode_program(&base64, ProgramEncoding::Hex).is_err());
+        assert!(decode_program("0xdeadbeef", ProgramEncoding::Base64).is_err());
+    }
+
+    #[test]
+    fn test_disasm_asm_roundtrip() {
+        let (cmr, program) = encoded_program();
+        let (disasm_cmr, assembly) = program_to_assembly(&program).unwrap();
+        assert_eq!(disasm_cmr, cmr);
+
+        // The witness node is named after its position in the assembly
+        let name = assembly
+            .lines()
+            .find_map(|line| line.split_once(":= witness").map(|(name, _)| name.trim()))
+            .expect("assembly has a witness node");
+        let witness = HashMap::from([(Arc::from(name), Value::u32(1000))]);
+        let node = assemble(&assembly, &witness).unwrap();
+        assert_eq!(node.cmr(), cmr);
+        let (reassembled, _) = node.encode_to_vec();
+        assert_eq!(reassembled, program);
+
+        // Disassembling the assembled program gives the same text
+        let (_, assembly_again) = program_to_assembly(&reassembled).unwrap();
+        assert_eq!(assembly_again, assembly);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use elements::taproot::TaprootSpendInfo;
+use elements::{OutPoint, Txid};
+use serde::Deserialize;
+
+use crate::{
+    commands::{
+        build::{compile_program, read_interface, TaprootArgs},
+        withdraw::{load_blinding_key, PaymentArgs},
+    },
+    esplora,
+    helpers::{load_checked_arguments, load_spend_witness},
+    keystore::KeyRef,
+    script::spend_info_address,
+    transaction::{spend, unblind_utxo, SpendInput, SpendPath},
+};
+
+#[derive(Args, Debug)]
+pub struct BatchArgs {
+    /// Path to the batch file (JSON) listing the contract outputs to spend
+    #[arg(long, value_name = "PATH")]
+    pub inputs: PathBuf,
+
+    #[command(flatten)]
+    pub payment: PaymentArgs,
+}
+
+/// Batch file: the contract outputs to spend, in input order
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchSpec {
+    inputs: Vec<BatchInput>,
+}
+
+/// Contract output to spend, paths are relative to the batch file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchInput {
+    /// Transaction ID of the output
+    txid: String,
+    /// Index of the output in the transaction, found by its address if not given
+    vout: Option<u32>,
+    /// Path to the program source
+    program: PathBuf,
+    /// Path to the program argument file
+    param: Option<PathBuf>,
+    /// Path to the witness file, not needed for key-path spends
+    witness: Option<PathBuf>,
+    /// Path to the taproot tree spec if the program is one of several leaves
+    taptree: Option<PathBuf>,
+    /// Internal key: x-only public key, keystore key name or `nums:<name>`
+    internal_key: Option<String>,
+    /// Spend with the internal key instead of the program
+    #[serde(default)]
+    key_path: bool,
+    /// Keystore key that blinds the address of a confidential output
+    blinding_key: Option<String>,
+}
+
+fn load_batch(path: &Path) -> Result<BatchSpec> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read batch file: {}", path.display()))?;
+    let mut spec: BatchSpec = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse batch file: {}", path.display()))?;
+    anyhow::ensure!(!spec.inputs.is_empty(), "Batch file has no inputs");
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    for input in &mut spec.inputs {
+        input.program = base_dir.join(&input.program);
+        input.param = input.param.as_ref().map(|p| base_dir.join(p));
+        input.witness = input.witness.as_ref().map(|p| base_dir.join(p));
+        input.taptree = input.taptree.as_ref().map(|p| base_dir.join(p));
+    }
+    Ok(spec)
+}
+
+/// Compile the program of an input and load its witness, or its key for a key-path spend.
+fn load_spend_path(input: &BatchInput) -> Result<(TaprootSpendInfo, SpendPath)> {
+    let interface = read_interface(&input.program, None)?;
+    let arguments = load_checked_arguments(input.param.as_ref(), &[], &interface)?;
+    let internal_key = input
+        .internal_key
+        .as_deref()
+        .map(KeyRef::from_str)
+        .transpose()
+        .map_err(|e| anyhow!("Invalid internal key: {}", e))?;
+    let taproot = TaprootArgs {
+        taptree: input.taptree.clone(),
+        internal_key,
+    };
+
+    let program = compile_program(&input.program, arguments, false, None)?;
+    let spend_info = taproot.spend_info(&program)?;
+    let path = if input.key_path {
+        let internal_key = taproot
+            .internal_key
+            .as_ref()
+            .ok_or_else(|| anyhow!("A key-path spend requires an internal key"))?;
+        SpendPath::Key(internal_key.keypair()?)
+    } else {
+        let witness = load_spend_witness(input.witness.as_ref(), &[], &interface)?;
+        SpendPath::Script { program, witness }
+    };
+    Ok((spend_info, path))
+}
+
+/// Load the spend path of an input and fetch the output it spends.
+fn load_input(input: &BatchInput) -> Result<SpendInput> {
+    let (spend_info, path) = load_spend_path(input)?;
+    let address = spend_info_address(&spend_info);
+    let blinding_key = load_blinding_key(input.blinding_key.as_ref())?;
+    let txid = Txid::from_str(&input.txid).map_err(|_| anyhow!("Invalid TXID format"))?;
+    let (outpoint, utxo) = match input.vout {
+        Some(vout) => {
+            let outpoint = OutPoint::new(txid, vout);
+            let utxo = esplora::fetch_output(&outpoint)?;
+            anyhow::ensure!(
+                utxo.script_pubkey == address.script_pubkey(),
+                "Output {} is not locked by the program address {}",
+                outpoint,
+                address
+            );
+            (outpoint, utxo)
+        }
+        None => esplora::fetch_utxo(&txid, &address)?,
+    };
+    let secrets = unblind_utxo(&utxo, blinding_key.as_ref())?;
+
+    Ok(SpendInput {
+        outpoint,
+        utxo,
+        secrets,
+        spend_info,
+        path,
+    })
+}
+
+/// Reject inputs spending the same output, which would make the transaction invalid.
+fn check_distinct_outpoints(outpoints: &[OutPoint]) -> Result<()> {
+    let mut seen = HashMap::new();
+    for (index, outpoint) in outpoints.iter().enumerate() {
+        if let Some(first) = seen.insert(*outpoint, index) {
+            anyhow::bail!(
+                "Inputs {} and {} spend the same output {}, give the `vout` of each output in the batch file",
+                first,
+                index,
+                outpoint
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn batch(args: BatchArgs) -> Result<()> {
+    let spec = load_batch(&args.inputs)?;
+    let outputs = args.payment.outputs()?;
+
+    let inputs = spec
+        .inputs
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            load_input(input).with_context(|| {
+                format!(
+                    "Failed to load input {} ({})",
+                    index,
+                    input.program.display()
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let outpoints: Vec<OutPoint> = inputs.iter().map(|input| input.outpoint).collect();
+    check_distinct_outpoints(&outpoints)?;
+    let fee_rate = args.payment.fee_rate()?;
+
+    println!("Spending {} inputs", inputs.len());
+    let tx = spend(&inputs, &outputs, fee_rate)?;
+    args.payment.send(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Address of `tests/data/sighash_none.simf` with the unspendable internal key
+    const PROGRAM_ADDRESS: &str = "tex1pvlydvg2lkew3068jyrsw469aac9lefnq0spjc7vqnxwrqrqmxrhsaythrt";
+
+    fn write_batch(dir: &Path, content: &str) -> PathBuf {
+        let path = dir.join("batch.json");
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn batch_input(program: &str) -> BatchInput {
+        BatchInput {
+            txid: "00".repeat(32),
+            vout: None,
+            program: PathBuf::from(program),
+            param: None,
+            witness: None,
+            taptree: None,
+            internal_key: None,
+            key_path: false,
+            blinding_key: None,
+        }
+    }
+
+    #[test]
+    fn test_load_batch_resolves_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_batch(
+            dir.path(),
+            r#"{"inputs": [
+                {"txid": "ab", "program": "a.simf", "witness": "a.wit", "taptree": "tree.json"},
+                {"txid": "cd", "vout": 2, "program": "sub/b.simf", "param": "b.args", "key_path": true}
+            ]}"#,
+        );
+
+        let spec = load_batch(&path).unwrap();
+        assert_eq!(spec.inputs.len(), 2);
+        assert_eq!(spec.inputs[0].program, dir.path().join("a.simf"));
+        assert_eq!(spec.inputs[0].witness, Some(dir.path().join("a.wit")));
+        assert_eq!(spec.inputs[0].taptree, Some(dir.path().join("tree.json")));
+        assert_eq!(spec.inputs[0].param, None);
+        assert!(!spec.inputs[0].key_path);
+        assert_eq!(spec.inputs[0].vout, None);
+        assert_eq!(spec.inputs[1].vout, Some(2));
+        assert_eq!(spec.inputs[1].program, dir.path().join("sub/b.simf"));
+        assert_eq!(spec.inputs[1].param, Some(dir.path().join("b.args")));
+        assert_eq!(spec.inputs[1].witness, None);
+        assert!(spec.inputs[1].key_path);
+    }
+
+    #[test]
+    fn test_load_batch_rejects_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let path = write_batch(dir.path(), r#"{"inputs": []}"#);
+        let error = load_batch(&path).unwrap_err();
+        assert_eq!(error.to_string(), "Batch file has no inputs");
+
+        let path = write_batch(
+            dir.path(),
+            r#"{"inputs": [{"txid": "ab", "program": "a.simf", "witnes": "a.wit"}]}"#,
+        );
+        let error = load_batch(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("unknown field `witnes`"));
+
+        let path = write_batch(dir.path(), r#"{"inputs": [{"program": "a.simf"}]}"#);
+        let error = load_batch(&path).unwrap_err();
+        assert!(format!("{:#}", error).contains("missing field `txid`"));
+    }
+
+    #[test]
+    fn test_load_spend_path() {
+        let mut input = batch_input("tests/data/sighash_none.simf");
+        input.witness = Some(PathBuf::from("tests/data/sighash_none.wit"));
+        let (spend_info, path) = load_spend_path(&input).unwrap();
+        assert_eq!(spend_info_address(&spend_info).to_string(), PROGRAM_ADDRESS);
+        assert!(matches!(path, SpendPath::Script { .. }));
+
+        // The internal key changes the address of the same program
+        input.internal_key =
+            Some("1b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f".to_string());
+        let (spend_info, _) = load_spend_path(&input).unwrap();
+        assert_eq!(
+            spend_info_address(&spend_info).to_string(),
+            "tex1p48fn8tvvxejghtfzc4lk8pjdfclla7m9ear46020xhyua9wa7nqsr6wpkj"
+        );
+    }
+
+    #[test]
+    fn test_load_spend_path_key_path_requires_internal_key() {
+        let mut input = batch_input("tests/data/sighash_none.simf");
+        input.key_path = true;
+        let Err(error) = load_spend_path(&input) else {
+            panic!("a key-path spend without internal key is loaded");
+        };
+        assert_eq!(
+            error.to_string(),
+            "A key-path spend requires an internal key"
+        );
+    }
+
+    #[test]
+    fn test_check_distinct_outpoints() {
+        let txid = Txid::from_str(&"ab".repeat(32)).unwrap();
+        let other = Txid::from_str(&"cd".repeat(32)).unwrap();
+        let outpoints = [
+            OutPoint::new(txid, 0),
+            OutPoint::new(txid, 1),
+            OutPoint::new(other, 0),
+        ];
+        check_distinct_outpoints(&outpoints).unwrap();
+
+        // Two inputs of the same program in the same transaction without `vout`
+        let outpoints = [
+            OutPoint::new(txid, 0),
+            OutPoint::new(other, 0),
+            OutPoint::new(txid, 0),
+        ];
+        let error = check_distinct_outpoints(&outpoints).unwrap_err();
+        let expected = format!("Inputs 0 and 2 spend the same output {},", outpoints[0]);
+        assert!(error.to_string().starts_with(&expected));
+    }
+}
@@ -1,36 +1,56 @@
 use anyhow::{Context, Result};
 use clap::Args;
+use elements::hashes::{sha256, Hash};
+use elements::taproot::TaprootSpendInfo;
+use elements::{Script, Transaction};
 use serde::{Deserialize, Serialize};
 use simfony::{dummy_env, Arguments, CompiledProgram, SatisfiedProgram, WitnessValues};
 use simplicity::human_encoding::Forest;
+use simplicity::jet::elements::ElementsEnv;
 use simplicity::jet::Elements;
-use simplicity::{BitIter, CommitNode};
+use simplicity::{BitIter, Cmr, CommitNode};
 use std::path::PathBuf;
 use std::process::Command;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::{fs, path::Path};
 
-use crate::helpers::{get_program_name, load_witness};
+use crate::commands::inspect_tx::read_tx;
+use crate::helpers::{
+    from_json, get_program_name, load_checked_arguments, load_checked_arguments_json,
+    load_checked_witness, parse_assignment,
+};
+use crate::interface::{program_interface, ProgramInterface};
+use crate::keystore::KeyRef;
+use crate::script::{
+    create_script, simplicity_control_block, simplicity_leaf_version, taproot_spending_info,
+    unspendable_key,
+};
+use crate::taptree::{self, Leaf, LeafRecord};
+use crate::transaction::{load_utxos, spend_env};
 
 #[derive(Args, Clone, Debug)]
 pub struct BuildArgs {
-    /// Path to the source file
-    /// Default: `./src/main.simf`
-    #[arg(long, default_value = "./src/main.simf")]
-    pub entrypoint: PathBuf,
-
-    /// Path to the mcpp include directory
-    /// If not provided, the program will be compiled without mcpp.
-    #[arg(long)]
-    pub mcpp_inc_path: Option<PathBuf>,
+    #[command(flatten)]
+    pub program: ProgramArgs,
 
     /// Path to the witness file
     #[arg(long)]
     pub witness: Option<PathBuf>,
 
+    /// Witness value in SimplicityHL syntax, overrides the witness file
+    /// (e.g. `--witness-value SIG=0x...`), can be repeated
+    #[arg(long = "witness-value", value_name = "NAME=VALUE", value_parser = parse_assignment)]
+    pub witness_values: Vec<(String, String)>,
+
     /// Prune the program using the provided witness
+    /// and the spending transaction (`--tx`) if given
     #[arg(long)]
     pub prune: bool,
 
+    #[command(flatten)]
+    pub env: EnvArgs,
+
     /// Write Simplicity assembly to a file
     #[arg(long)]
     pub assembly: bool,
@@ -40,20 +60,274 @@ pub struct BuildArgs {
     pub target_dir: PathBuf,
 }
 
+/// Program source and the values of its parameters
+#[derive(Args, Clone, Debug)]
+pub struct ProgramArgs {
+    /// Path to the source file
+    /// Default: `./src/main.simf`
+    #[arg(long, default_value = "./src/main.simf")]
+    pub entrypoint: PathBuf,
+
+    /// Path to the mcpp include directory
+    /// If not provided, the program will be compiled without mcpp.
+    #[arg(long)]
+    pub mcpp_inc_path: Option<PathBuf>,
+
+    /// Path to file with arguments
+    #[arg(long)]
+    pub param: Option<PathBuf>,
+
+    /// Argument value in SimplicityHL syntax, overrides the argument file
+    /// (e.g. `--arg MIN_HEIGHT=1000`), can be repeated
+    #[arg(long = "arg", value_name = "NAME=VALUE", value_parser = parse_assignment)]
+    pub arg_values: Vec<(String, String)>,
+}
+
+impl ProgramArgs {
+    /// Preprocessed source of the program.
+    pub fn source(&self) -> Result<String> {
+        read_source(&self.entrypoint, self.mcpp_inc_path.clone())
+    }
+
+    /// Parameters and witnesses declared by the program.
+    pub fn interface(&self) -> Result<ProgramInterface> {
+        read_interface(&self.entrypoint, self.mcpp_inc_path.clone())
+    }
+
+    /// Arguments from the argument file and `--arg`, checked against the interface.
+    pub fn arguments(&self, interface: &ProgramInterface) -> Result<Arguments> {
+        load_checked_arguments(self.param.as_ref(), &self.arg_values, interface)
+    }
+
+    /// Compile the program with the given arguments.
+    pub fn compile(&self, arguments: Arguments, debug_symbols: bool) -> Result<CompiledProgram> {
+        compile_program(
+            &self.entrypoint,
+            arguments,
+            debug_symbols,
+            self.mcpp_inc_path.clone(),
+        )
+    }
+}
+
+/// Spending transaction the program is executed against
+#[derive(Args, Clone, Debug, Default)]
+pub struct EnvArgs {
+    /// Path to the spending transaction (hex), used to prune and run the program
+    #[arg(long, value_name = "PATH", requires = "utxos")]
+    pub tx: Option<PathBuf>,
+
+    /// Path to a JSON file with the outputs spent by the transaction, one per input
+    #[arg(long, value_name = "PATH", requires = "tx")]
+    pub utxos: Option<PathBuf>,
+
+    /// Index of the transaction input that spends the program
+    #[arg(long, visible_alias = "input", default_value_t = 0)]
+    pub input_index: u32,
+
+    #[command(flatten)]
+    pub taproot: TaprootArgs,
+}
+
+/// Taproot output that commits to the program
+#[derive(Args, Clone, Debug, Default)]
+pub struct TaprootArgs {
+    /// Path to a taproot tree spec (JSON) if the program is one of several leaves
+    #[arg(long, value_name = "PATH")]
+    pub taptree: Option<PathBuf>,
+
+    /// Taproot internal key: x-only public key (hex) or keystore key name.
+    /// Uses an unspendable key by default, which disables key-path spending
+    #[arg(long, value_name = "KEY")]
+    pub internal_key: Option<KeyRef>,
+}
+
+impl EnvArgs {
+    /// Environment of the spending transaction, if one is provided.
+    pub fn spend_env(
+        &self,
+        program: &CompiledProgram,
+    ) -> Result<Option<ElementsEnv<Arc<Transaction>>>> {
+        let (Some(tx_path), Some(utxos_path)) = (&self.tx, &self.utxos) else {
+            return Ok(None);
+        };
+        let tx = read_tx(Some(tx_path))?;
+        let utxos = load_utxos(utxos_path)?;
+        let spend_info = self.taproot.spend_info(program)?;
+        spend_env(tx, utxos, self.input_index, program, &spend_info).map(Some)
+    }
+}
+
+impl TaprootArgs {
+    /// Taproot tree the program output commits to: the taptree if provided,
+    /// otherwise a tree with the program as its only leaf.
+    pub fn spend_info(&self, program: &CompiledProgram) -> Result<TaprootSpendInfo> {
+        self.script_spend_info(create_script(program)?)
+    }
+
+    /// Taproot tree committing to the leaf `script`, see [`TaprootArgs::spend_info`].
+    pub fn script_spend_info(&self, script: Script) -> Result<TaprootSpendInfo> {
+        let leaves = self.taptree.as_deref().map(load_taptree).transpose()?;
+        self.leaves_spend_info(script, leaves.as_deref())
+    }
+
+    /// Taproot tree of the given leaves, which must include `script`,
+    /// or a tree with `script` as its only leaf.
+    pub fn leaves_spend_info(
+        &self,
+        script: Script,
+        leaves: Option<&[Leaf]>,
+    ) -> Result<TaprootSpendInfo> {
+        let internal_key = match &self.internal_key {
+            Some(key) => key.public_key()?,
+            None => unspendable_key(),
+        };
+        let Some(leaves) = leaves else {
+            return taproot_spending_info(script, internal_key);
+        };
+        let spend_info = taptree::spend_info(leaves, internal_key)?;
+        // Fail early if the program is missing from the tree
+        simplicity_control_block(script, &spend_info)?;
+        Ok(spend_info)
+    }
+}
+
+/// Load a taptree spec, compiling its program leaves with their argument files.
+pub fn load_taptree(path: &Path) -> Result<Vec<Leaf>> {
+    let spec = taptree::load_spec(path)?;
+    spec.leaves
+        .iter()
+        .map(|leaf| {
+            if let Some(tapscript) = leaf.tapscript_leaf()? {
+                return Ok(tapscript);
+            }
+            let program = leaf.program.as_ref().expect("checked by tapscript_leaf");
+            let interface = read_interface(program, None)?;
+            let arguments = load_checked_arguments(leaf.param.as_ref(), &[], &interface)?;
+            let compiled = compile_program(program, arguments, false, None).with_context(|| {
+                format!("Failed to compile taptree leaf: {}", program.display())
+            })?;
+            Ok(Leaf {
+                name: program.display().to_string(),
+                script: create_script(&compiled)?,
+                version: simplicity_leaf_version(),
+                placement: leaf.placement()?,
+            })
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BuildArtifacts {
     pub program: Vec<u8>,
     pub witness: Option<Vec<u8>>,
+    /// Inputs of the build, missing in artifacts that were not compiled from source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BuildMetadata>,
 }
 
-fn format_node_bounds(bounds: &simplicity::NodeBounds) -> String {
+/// Revision of the SimplicityHL compiler, as resolved in Cargo.lock (see `build.rs`)
+pub const COMPILER_REVISION: &str = env!("SIMFONY_REVISION");
+
+/// Everything needed to reproduce the commitment of a program from its source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildMetadata {
+    pub simply_version: String,
+    pub compiler_revision: String,
+    /// mcpp include directory, if the source was preprocessed
+    pub mcpp_inc_path: Option<PathBuf>,
+    /// SHA-256 of the (preprocessed) source code
+    pub source_sha256: String,
+    /// Program arguments in the argument file format
+    pub arguments: serde_json::Value,
+    pub cmr: String,
+    /// Taproot internal key (x-only, hex), the unspendable key if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub internal_key: Option<String>,
+    /// Leaves of the taproot tree, if the program is one of several leaves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taptree: Option<Vec<LeafRecord>>,
+}
+
+impl BuildMetadata {
+    pub fn new(
+        source: &str,
+        mcpp_inc_path: Option<PathBuf>,
+        arguments: serde_json::Value,
+        cmr: Cmr,
+        internal_key: Option<&KeyRef>,
+        taptree: Option<&[Leaf]>,
+    ) -> Result<Self> {
+        let internal_key = match internal_key {
+            Some(key) => Some(key.public_key()?.to_string()),
+            None => None,
+        };
+        Ok(Self {
+            simply_version: env!("CARGO_PKG_VERSION").to_string(),
+            compiler_revision: COMPILER_REVISION.to_string(),
+            mcpp_inc_path,
+            source_sha256: sha256::Hash::hash(source.as_bytes()).to_string(),
+            arguments,
+            cmr: cmr.to_string(),
+            internal_key,
+            taptree: taptree.map(|leaves| leaves.iter().map(LeafRecord::from).collect()),
+        })
+    }
+
+    /// Taproot settings of the build: the recorded internal key
+    pub fn taproot_args(&self) -> Result<TaprootArgs> {
+        let internal_key = self
+            .internal_key
+            .as_deref()
+            .map(KeyRef::from_str)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid recorded internal key: {}", e))?;
+        Ok(TaprootArgs {
+            internal_key,
+            ..Default::default()
+        })
+    }
+
+    /// Leaves of the recorded taproot tree. If `program` is given,
+    /// it replaces the leaf of the recorded program.
+    pub fn taptree(&self, program: Option<&Script>) -> Result<Option<Vec<Leaf>>> {
+        let Some(records) = &self.taptree else {
+            return Ok(None);
+        };
+        let mut leaves = records
+            .iter()
+            .map(LeafRecord::to_leaf)
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(program) = program {
+            let leaf = leaves
+                .iter_mut()
+                .find(|leaf| hex::encode(leaf.script.as_bytes()) == self.cmr)
+                .ok_or_else(|| anyhow::anyhow!("Recorded taptree has no leaf for the program"))?;
+            leaf.script = program.clone();
+        }
+        Ok(Some(leaves))
+    }
+}
+
+pub(crate) fn format_node_bounds(bounds: &simplicity::NodeBounds) -> String {
     format!(
         "Node bounds:\n  Extra cells: {}\n  Extra frames: {}\n  CPU cost: {}",
         bounds.extra_cells, bounds.extra_frames, bounds.cost
     )
 }
 
-fn write_build_output(
+/// Decode a program and convert it to Simplicity assembly (human encoding).
+/// Returns the CMR of the program along with the assembly.
+pub fn program_to_assembly(program: &[u8]) -> Result<(Cmr, String)> {
+    let iter = BitIter::from(program.iter().copied());
+    let commit =
+        CommitNode::<Elements>::decode(iter).with_context(|| "failed to decode program")?;
+    let cmr = commit.cmr();
+    let prog = Forest::<Elements>::from_program(commit);
+    Ok((cmr, prog.string_serialize()))
+}
+
+pub(crate) fn write_build_output(
     target_dir: &PathBuf,
     program_name: &str,
     artifacts: BuildArtifacts,
@@ -79,13 +353,11 @@ fn write_build_output(
         .with_context(|| format!("Failed to write output file: {}", output_file.display()))?;
 
     if assembly {
-        let iter = BitIter::from(artifacts.program.into_iter());
-        let commit = CommitNode::decode(iter).with_context(|| "failed to decode program")?;
-        let prog = Forest::<Elements>::from_program(commit);
+        let (_, assembly) = program_to_assembly(&artifacts.program)?;
 
         let assembly_file = target_dir.join(program_name).with_extension("simp");
 
-        fs::write(&assembly_file, prog.string_serialize()).with_context(|| {
+        fs::write(&assembly_file, assembly).with_context(|| {
             format!("Failed to write assembly file: {}", assembly_file.display())
         })?;
 
@@ -96,12 +368,8 @@ fn write_build_output(
     Ok(())
 }
 
-pub fn compile_program(
-    source_path: &Path,
-    arguments: Arguments,
-    debug_symbols: bool,
-    mcpp_inc_path: Option<PathBuf>,
-) -> Result<CompiledProgram> {
+/// Read the program source, running it through mcpp if an include path is provided.
+pub fn read_source(source_path: &Path, mcpp_inc_path: Option<PathBuf>) -> Result<String> {
     let source = if let Some(mcpp_inc_path) = mcpp_inc_path {
         // Try to find mcpp binary in the path
         let mcpp_path = which::which("mcpp").with_context(|| "mcpp binary not found")?;
@@ -132,6 +400,26 @@ pub fn compile_program(
             .with_context(|| format!("Failed to read source file: {}", source_path.display()))?
     };
 
+    Ok(source)
+}
+
+/// Read the program source and collect its declared parameters and witnesses.
+pub fn read_interface(
+    source_path: &Path,
+    mcpp_inc_path: Option<PathBuf>,
+) -> Result<ProgramInterface> {
+    let source = read_source(source_path, mcpp_inc_path)?;
+    program_interface(&source)
+        .with_context(|| format!("Failed to analyze program: {}", source_path.display()))
+}
+
+pub fn compile_program(
+    source_path: &Path,
+    arguments: Arguments,
+    debug_symbols: bool,
+    mcpp_inc_path: Option<PathBuf>,
+) -> Result<CompiledProgram> {
+    let source = read_source(source_path, mcpp_inc_path)?;
     let compiled = CompiledProgram::new(source, arguments, debug_symbols)
         .map_err(|e| anyhow::anyhow!(e))
         .with_context(|| "Failed to compile program")?;
@@ -139,40 +427,49 @@ pub fn compile_program(
     Ok(compiled)
 }
 
+/// Satisfy the program with the witness, pruning it against `env` if requested.
 pub fn satisfy_program(
     compiled: CompiledProgram,
     witness: WitnessValues,
     prune: bool,
+    env: Option<&ElementsEnv<Arc<Transaction>>>,
 ) -> Result<SatisfiedProgram> {
-    if prune {
-        println!("WARNING: program will be pruned using the provided witness, it might not work with a different one.");
-        // TODO(m_kus): provide env via CLI
-        let env = dummy_env::dummy();
-        compiled
-            .satisfy_with_env(witness, Some(&env))
-            .map_err(|e| anyhow::anyhow!(e))
-    } else {
-        compiled.satisfy(witness).map_err(|e| anyhow::anyhow!(e))
+    if !prune {
+        return compiled.satisfy(witness).map_err(|e| anyhow::anyhow!(e));
     }
+
+    println!("WARNING: program will be pruned using the provided witness, it might not work with a different one.");
+    let dummy;
+    let env = match env {
+        Some(env) => env,
+        None => {
+            println!("WARNING: no spending transaction provided (--tx), pruning against a dummy one. The pruned program might not be valid on chain.");
+            dummy = dummy_env::dummy();
+            &dummy
+        }
+    };
+    compiled
+        .satisfy_with_env(witness, Some(env))
+        .map_err(|e| anyhow::anyhow!(e))
 }
 
 pub fn build_program(
-    source_path: &Path,
+    source: &str,
     witness: Option<WitnessValues>,
-    arguments: Option<Arguments>,
+    arguments: Arguments,
     prune: bool,
+    env_args: &EnvArgs,
     debug_symbols: bool,
-    mcpp_inc_path: Option<PathBuf>,
-) -> Result<BuildArtifacts> {
-    let compiled = compile_program(
-        source_path,
-        arguments.unwrap_or_default(),
-        debug_symbols,
-        mcpp_inc_path,
-    )?;
+) -> Result<(BuildArtifacts, Cmr)> {
+    let compiled = CompiledProgram::new(source, arguments, debug_symbols)
+        .map_err(|e| anyhow::anyhow!(e))
+        .with_context(|| "Failed to compile program")?;
+    // Pruning keeps the CMR, the commitment is the one of the full program
+    let cmr = compiled.commit().cmr();
 
     if let Some(witness) = witness {
-        let satisfied = satisfy_program(compiled, witness, prune)?;
+        let env = env_args.spend_env(&compiled)?;
+        let satisfied = satisfy_program(compiled, witness, prune, env.as_ref())?;
         let node = satisfied.redeem();
         println!("{}", format_node_bounds(&node.bounds()));
 
@@ -186,23 +483,36 @@ pub fn build_program(
             .len();
         println!("Required padding size: {}", padding_size);
 
-        Ok(BuildArtifacts {
-            program: program_bytes,
-            witness: Some(witness_bytes),
-        })
+        Ok((
+            BuildArtifacts {
+                program: program_bytes,
+                witness: Some(witness_bytes),
+                metadata: None,
+            },
+            cmr,
+        ))
     } else {
-        Ok(BuildArtifacts {
-            program: compiled.commit().encode_to_vec(),
-            witness: None,
-        })
+        Ok((
+            BuildArtifacts {
+                program: compiled.commit().encode_to_vec(),
+                witness: None,
+                metadata: None,
+            },
+            cmr,
+        ))
     }
 }
 
 // TODO(m_kus): serialize debug symbols and allow to load artifacts instead of rebuilding for run
 #[allow(dead_code)]
-pub fn load_artifacts(target_dir: &PathBuf, program_name: &str) -> Result<BuildArtifacts> {
+pub fn load_artifacts(target_dir: &Path, program_name: &str) -> Result<BuildArtifacts> {
     let output_file = target_dir.join(program_name).with_extension("json");
-    let json_content = fs::read_to_string(&output_file).with_context(|| {
+    load_artifacts_from(&output_file)
+}
+
+/// Load build artifacts from a JSON file.
+pub fn load_artifacts_from(output_file: &Path) -> Result<BuildArtifacts> {
+    let json_content = fs::read_to_string(output_file).with_context(|| {
         format!(
             "Failed to read build artifacts from {}",
             output_file.display()
@@ -220,19 +530,38 @@ pub fn load_artifacts(target_dir: &PathBuf, program_name: &str) -> Result<BuildA
 }
 
 pub fn build(args: BuildArgs) -> Result<()> {
-    let witness = if let Some(witness_path) = args.witness {
-        Some(load_witness(Some(&witness_path))?)
+    let program = &args.program;
+    let source = program.source()?;
+    let interface = program_interface(&source).with_context(|| {
+        format!(
+            "Failed to analyze program: {}",
+            program.entrypoint.display()
+        )
+    })?;
+    let arguments_json =
+        load_checked_arguments_json(program.param.as_ref(), &program.arg_values, &interface)?;
+    let arguments = from_json(&arguments_json).with_context(|| "Failed to parse arguments")?;
+    let witness = if args.witness.is_some() || !args.witness_values.is_empty() {
+        Some(load_checked_witness(
+            args.witness.as_ref(),
+            &args.witness_values,
+            &interface,
+        )?)
     } else {
         None
     };
-    let artifacts = build_program(
-        &args.entrypoint,
-        witness,
-        None,
-        args.prune,
-        false,
-        args.mcpp_inc_path,
-    )?;
-    let program_name = get_program_name(&args.entrypoint)?;
+    let (mut artifacts, cmr) =
+        build_program(&source, witness, arguments, args.prune, &args.env, false)?;
+    let taproot = &args.env.taproot;
+    let taptree = taproot.taptree.as_deref().map(load_taptree).transpose()?;
+    artifacts.metadata = Some(BuildMetadata::new(
+        &source,
+        program.mcpp_inc_path.clone(),
+        arguments_json,
+        cmr,
+        taproot.internal_key.as_ref(),
+        taptree.as_deref(),
+    )?);
+    let program_name = get_program_name(&program.entrypoint)?;
     write_build_output(&args.target_dir, &program_name, artifacts, args.assembly)
 }
@@ -2,24 +2,55 @@ use anyhow::Result;
 use clap::Args;
 
 use crate::{
-    commands::{build::compile_program, BuildArgs},
-    script::{create_p2tr_address, unspendable_key},
+    commands::{build::load_taptree, BuildArgs},
+    keystore::{keystore_dir, load_keypair},
+    script::spend_info_address,
+    taptree::Placement,
 };
 
 #[derive(Args, Debug)]
 pub struct DepositArgs {
     #[command(flatten)]
     pub build: BuildArgs,
+
+    /// Keystore key blinding the deposit address, which makes it confidential
+    #[arg(long, value_name = "NAME")]
+    pub blinding_key: Option<String>,
 }
 
 pub fn deposit(args: DepositArgs) -> Result<()> {
-    let program = compile_program(
-        &args.build.entrypoint,
-        Default::default(),
-        false,
-        args.build.mcpp_inc_path,
-    )?;
-    let address = create_p2tr_address(program, unspendable_key())?;
+    let interface = args.build.program.interface()?;
+    let arguments = args.build.program.arguments(&interface)?;
+    let program = args.build.program.compile(arguments, false)?;
+    let taproot = &args.build.env.taproot;
+    let spend_info = taproot.spend_info(&program)?;
+    let mut address = spend_info_address(&spend_info);
+    if let Some(name) = &args.blinding_key {
+        let blinding_keypair = load_keypair(&keystore_dir()?, name)?;
+        address = address.to_confidential(blinding_keypair.public_key());
+    }
+    if let Some(taptree_path) = &taproot.taptree {
+        println!("Taptree leaves:");
+        for leaf in load_taptree(taptree_path)? {
+            let placement = match leaf.placement {
+                Placement::Weight(weight) => format!("weight {}", weight),
+                Placement::Depth(depth) => format!("depth {}", depth),
+            };
+            println!(
+                "  {} ({}, script {})",
+                leaf.name,
+                placement,
+                hex::encode(leaf.script.as_bytes())
+            );
+        }
+        println!();
+    }
+    if taproot.internal_key.is_some() {
+        println!(
+            "Internal key: {} (key-path spend with `simply withdraw --key-path`)\n",
+            spend_info.internal_key()
+        );
+    }
     println!(
         "P2TR address: {}\n\nGo to https://liquidtestnet.com/faucet to get some testnet coins.",
         address
@@ -0,0 +1,50 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::load_project_config;
+use crate::formatter::format_source;
+use crate::helpers::find_simf_files;
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    /// Path to the source file, all `*.simf` files in its directory are formatted
+    /// Default: `./src/main.simf`
+    #[arg(long, default_value = "./src/main.simf")]
+    pub entrypoint: PathBuf,
+
+    /// Do not write the files, exit with an error if any of them is not formatted
+    #[arg(long)]
+    pub check: bool,
+}
+
+pub fn fmt(args: FmtArgs) -> Result<()> {
+    let source_dir = args.entrypoint.parent().unwrap();
+    let config = load_project_config(source_dir)?;
+    let simf_files = find_simf_files(source_dir.to_str().unwrap())?;
+
+    let mut unformatted = Vec::new();
+    for file_path in simf_files {
+        let content = fs::read_to_string(&file_path)
+            .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
+        let formatted = format_source(&content, &config.fmt);
+        if formatted == content {
+            continue;
+        }
+
+        if args.check {
+            println!("Diff in {}", file_path.display());
+        } else {
+            fs::write(&file_path, formatted)
+                .with_context(|| format!("Failed to write file: {}", file_path.display()))?;
+            println!("Formatted {}", file_path.display());
+        }
+        unformatted.push(file_path);
+    }
+
+    if args.check && !unformatted.is_empty() {
+        anyhow::bail!("{} file(s) need formatting", unformatted.len());
+    }
+    Ok(())
+}
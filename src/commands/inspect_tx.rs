@@ -0,0 +1,162 @@
+use std::io::Read;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use elements::{encode, Address, Script, Transaction};
+use simplicity::jet::Elements;
+use simplicity::{BitIter, RedeemNode};
+
+use crate::{
+    commands::build::{format_node_bounds, program_to_assembly},
+    script::{control_block_commits_to, p2tr_output_key, simplicity_leaf_version},
+    transaction::{load_utxos, parse_spend_witness},
+};
+
+#[derive(Args, Debug)]
+pub struct InspectTxArgs {
+    /// Path to a file with the transaction hex (reads stdin if omitted or `-`)
+    #[arg(long)]
+    pub tx: Option<PathBuf>,
+
+    /// Index of the input to inspect
+    #[arg(long, default_value_t = 0)]
+    pub input: usize,
+
+    /// Path to a JSON file with the outputs spent by the transaction, one per input,
+    /// used to verify the control block
+    #[arg(long, value_name = "PATH", conflicts_with = "prevout_address")]
+    pub utxos: Option<PathBuf>,
+
+    /// Address of the spent output, used to verify the control block
+    #[arg(long)]
+    pub prevout_address: Option<String>,
+}
+
+/// Read a hex-encoded transaction from a file or stdin.
+pub fn read_tx(path: Option<&PathBuf>) -> Result<Transaction> {
+    let tx_hex = match path {
+        Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read transaction file: {}", path.display()))?,
+        _ => {
+            let mut tx_hex = String::new();
+            std::io::stdin()
+                .read_to_string(&mut tx_hex)
+                .with_context(|| "Failed to read transaction from stdin")?;
+            tx_hex
+        }
+    };
+    let tx_bytes = hex::decode(tx_hex.trim()).with_context(|| "Transaction is not valid hex")?;
+    encode::deserialize(&tx_bytes).with_context(|| "Failed to decode transaction")
+}
+
+/// Script pubkey of the output spent by the inspected input, if it is given.
+fn spent_script_pubkey(args: &InspectTxArgs) -> Result<Option<Script>> {
+    if let Some(utxos) = &args.utxos {
+        let utxo = load_utxos(utxos)?
+            .into_iter()
+            .nth(args.input)
+            .ok_or_else(|| anyhow!("UTXO file has no output for input {}", args.input))?;
+        return Ok(Some(utxo.script_pubkey));
+    }
+    args.prevout_address
+        .as_deref()
+        .map(|address| {
+            Address::from_str(address)
+                .map(|address| address.script_pubkey())
+                .map_err(|_| anyhow!("Invalid prevout address format"))
+        })
+        .transpose()
+}
+
+fn check_mark(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "MISMATCH"
+    }
+}
+
+pub fn inspect_tx(args: InspectTxArgs) -> Result<()> {
+    let tx = read_tx(args.tx.as_ref())?;
+    let input = tx.input.get(args.input).ok_or_else(|| {
+        anyhow!(
+            "Input index {} is out of range, transaction has {} inputs",
+            args.input,
+            tx.input.len()
+        )
+    })?;
+
+    let stack = &input.witness.script_witness;
+    let spend = parse_spend_witness(stack)?;
+
+    println!("Transaction ID: {}", tx.txid());
+    println!("Input {}: spends {}", args.input, input.previous_output);
+    println!("Witness stack: {} elements", stack.len());
+    println!("  Simplicity witness: {} bytes", spend.witness.len());
+    println!("  Program: {} bytes", spend.program.len());
+    println!("  CMR script: {}", hex::encode(spend.script.as_bytes()));
+    println!(
+        "  Control block: {} bytes (leaf version: {:#04x}, internal key: {}, merkle path length: {})",
+        spend.control_block.serialize().len(),
+        spend.control_block.leaf_version.as_u8(),
+        spend.control_block.internal_key,
+        spend.control_block.merkle_branch.as_inner().len()
+    );
+    match &spend.annex {
+        Some(annex) => println!("  Annex (padding): {} bytes", annex.len()),
+        None => println!("  Annex (padding): none"),
+    }
+
+    let node = RedeemNode::<Elements>::decode(
+        BitIter::from(spend.program.iter().copied()),
+        BitIter::from(spend.witness.iter().copied()),
+    )
+    .map_err(|e| anyhow!("Failed to decode Simplicity program: {}", e))?;
+    let bounds = node.bounds();
+
+    println!("\nCMR: {}", node.cmr());
+    println!("{}", format_node_bounds(&bounds));
+
+    println!("\nChecks:");
+    println!(
+        "  CMR matches script: {}",
+        check_mark(spend.script.as_bytes() == node.cmr().as_ref())
+    );
+    println!(
+        "  Simplicity leaf version: {}",
+        check_mark(spend.control_block.leaf_version == simplicity_leaf_version())
+    );
+    println!(
+        "  Witness budget: {}",
+        check_mark(bounds.cost.is_budget_valid(stack))
+    );
+
+    match spent_script_pubkey(&args)? {
+        Some(script_pubkey) => {
+            let commits = match p2tr_output_key(&script_pubkey) {
+                Some(output_key) => {
+                    println!("  Output key (spent output): {}", output_key);
+                    control_block_commits_to(&spend.control_block, &spend.script, output_key)
+                }
+                None => {
+                    println!("  Spent output is not a P2TR output: {}", script_pubkey);
+                    false
+                }
+            };
+            println!(
+                "  Control block commits to the spent output: {}",
+                check_mark(commits)
+            );
+        }
+        None => println!(
+            "  Control block: provide --utxos or --prevout-address to verify the commitment"
+        ),
+    }
+
+    let (_, assembly) = program_to_assembly(&spend.program)?;
+    println!("\nAssembly:\n{}", assembly);
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use elements::secp256k1_zkp::{rand::rngs::OsRng, SecretKey};
+
+use crate::keystore::{keystore_dir, list_keys, load_keypair, parse_secret_key, store_key};
+
+#[derive(Subcommand, Debug)]
+pub enum KeyCommand {
+    /// Generate a new key and store it in the keystore
+    New(KeyNewArgs),
+
+    /// Import an existing secret key into the keystore
+    Import(KeyImportArgs),
+
+    /// List the keys in the keystore with their x-only public keys
+    List,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyNewArgs {
+    /// Name of the key
+    pub name: String,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyImportArgs {
+    /// Name of the key
+    pub name: String,
+
+    /// Secret key, hex-encoded (32 bytes). If omitted, it is read from stdin
+    #[arg(long, value_name = "HEX")]
+    pub secret: Option<String>,
+}
+
+pub fn key(command: KeyCommand) -> Result<()> {
+    let dir = keystore_dir()?;
+    match command {
+        KeyCommand::New(args) => {
+            let secret_key = SecretKey::new(&mut OsRng);
+            add_key(&dir, &args.name, &secret_key)
+        }
+        KeyCommand::Import(args) => {
+            let secret = match args.secret {
+                Some(secret) => secret,
+                None => {
+                    let mut secret = String::new();
+                    std::io::stdin()
+                        .read_to_string(&mut secret)
+                        .with_context(|| "Failed to read secret key from stdin")?;
+                    secret
+                }
+            };
+            add_key(&dir, &args.name, &parse_secret_key(&secret)?)
+        }
+        KeyCommand::List => {
+            let names = list_keys(&dir)?;
+            if names.is_empty() {
+                println!("No keys in {}", dir.display());
+            }
+            for name in names {
+                let keypair = load_keypair(&dir, &name)?;
+                println!("{}: {}", name, keypair.x_only_public_key().0);
+            }
+            Ok(())
+        }
+    }
+}
+
+fn add_key(dir: &std::path::Path, name: &str, secret_key: &SecretKey) -> Result<()> {
+    let path = store_key(dir, name, secret_key)?;
+    let keypair = load_keypair(dir, name)?;
+    println!("Key `{}` written to: {}", name, path.display());
+    println!(
+        "Public key (x-only): {}",
+        hex::encode(keypair.x_only_public_key().0.serialize())
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use clap::Args;
+
+use crate::{
+    commands::BuildArgs,
+    diagnostics::SourceFile,
+    lint::{lint as lint_program, LintConfig},
+};
+
+#[derive(Args, Debug)]
+pub struct LintArgs {
+    #[command(flatten)]
+    pub build: BuildArgs,
+
+    /// Report jets that cost more than this (in milliweight units)
+    #[arg(long, default_value_t = LintConfig::default().max_jet_cost)]
+    pub max_jet_cost: u32,
+
+    /// Exit with an error if any warnings are found
+    #[arg(long)]
+    pub deny_warnings: bool,
+}
+
+pub fn lint(args: LintArgs) -> Result<()> {
+    let program = &args.build.program;
+    let interface = program.interface()?;
+    let arguments = program.arguments(&interface)?;
+    let compiled = program.compile(arguments.clone(), true)?;
+    let source = SourceFile::new(program.entrypoint.display().to_string(), program.source()?);
+
+    let config = LintConfig {
+        max_jet_cost: args.max_jet_cost,
+    };
+    let diagnostics = lint_program(&source, &compiled, &arguments, &config);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic.render(&source));
+    }
+
+    if diagnostics.is_empty() {
+        println!("No warnings");
+    } else {
+        println!("warning: {} warning(s) emitted", diagnostics.len());
+        if args.deny_warnings {
+            anyhow::bail!("Lint failed due to warnings");
+        }
+    }
+    Ok(())
+}
@@ -1,17 +1,41 @@
 use clap::{Parser, Subcommand};
 
+mod asm;
+mod batch;
 mod build;
 mod deposit;
+mod fmt;
+mod inspect_tx;
+mod key;
+mod lint;
+mod new;
+mod nums;
+mod pset;
 mod run;
+mod sighash;
 mod sign;
+mod template;
 mod test;
+mod verify;
 mod withdraw;
 
+pub use asm::{asm, disasm, AsmArgs, DisasmArgs};
+pub use batch::{batch, BatchArgs};
 pub use build::{build, BuildArgs};
 pub use deposit::{deposit, DepositArgs};
-pub use run::{run, Logging, RunArgs};
+pub use fmt::{fmt, FmtArgs};
+pub use inspect_tx::{inspect_tx, InspectTxArgs};
+pub use key::{key, KeyCommand};
+pub use lint::{lint, LintArgs};
+pub use new::{init, new, InitArgs, NewArgs};
+pub use nums::{nums, NumsCommand};
+pub use pset::{pset, PsetCommand};
+pub use run::{run, Logging, RunArgs, TraceArgs};
+pub use sighash::{sighash, SighashArgs};
 pub use sign::{sign, SignArgs};
+pub use template::{arguments, witness, TemplateCommand};
 pub use test::{test, TestArgs};
+pub use verify::{verify, VerifyArgs};
 pub use withdraw::{withdraw, WithdrawArgs};
 
 #[derive(Parser)]
@@ -24,6 +48,12 @@ pub struct Cli {
 
 #[derive(Subcommand)]
 pub enum Commands {
+    /// Create a new project from a contract template
+    New(NewArgs),
+
+    /// Create a project from a contract template in an existing directory
+    Init(InitArgs),
+
     /// Build a SimplicityHL program
     Build(BuildArgs),
 
@@ -39,6 +69,61 @@ pub enum Commands {
     /// Spend a transaction output
     Withdraw(WithdrawArgs),
 
+    /// Spend several contract outputs in one transaction
+    Batch(BatchArgs),
+
+    /// Compute the `jet::sig_all_hash` message of a transaction input
+    Sighash(SighashArgs),
+
     /// Sign a message
     Sign(SignArgs),
+
+    /// Format SimplicityHL source files
+    Fmt(FmtArgs),
+
+    /// Check a SimplicityHL program for common contract mistakes
+    Lint(LintArgs),
+
+    /// Assemble a Simplicity program from its human encoding (`.simp`)
+    Asm(AsmArgs),
+
+    /// Disassemble a Simplicity program into its human encoding (`.simp`)
+    Disasm(DisasmArgs),
+
+    /// Decode the Simplicity spend of a transaction input
+    InspectTx(InspectTxArgs),
+
+    /// Verify that an address or CMR commits to the program
+    Verify(VerifyArgs),
+
+    /// Manage the keystore
+    Key {
+        #[command(subcommand)]
+        command: KeyCommand,
+    },
+
+    /// Manage randomized unspendable (NUMS) internal keys
+    Nums {
+        #[command(subcommand)]
+        command: NumsCommand,
+    },
+
+    /// Build a transaction collaboratively with a PSET file
+    Pset {
+        #[command(subcommand)]
+        command: PsetCommand,
+    },
+
+    /// Manage witness files
+    Witness {
+        #[command(subcommand)]
+        command: TemplateCommand,
+    },
+
+    /// Manage argument files
+    #[command(name = "args")]
+    Arguments {
+        #[command(subcommand)]
+        command: TemplateCommand,
+    },
 }
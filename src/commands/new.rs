@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::interface::{program_interface, template};
+
+#[derive(Args, Debug)]
+pub struct NewArgs {
+    /// Path of the project directory to create, its name is the project name
+    pub path: PathBuf,
+
+    /// Contract template
+    #[arg(long, value_enum, default_value_t = Template::P2pk)]
+    pub template: Template,
+}
+
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// Path of the existing project directory
+    #[arg(default_value = ".")]
+    pub path: PathBuf,
+
+    /// Contract template
+    #[arg(long, value_enum, default_value_t = Template::P2pk)]
+    pub template: Template,
+}
+
+/// Contract templates embedded in the binary
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Template {
+    /// Pay to public key
+    P2pk,
+    /// Hash time-locked contract
+    Htlc,
+    /// 2-of-3 multisig
+    Multisig,
+    /// Pay to public key after an absolute timelock
+    Timelock,
+    /// Hot key with a relative timelock, cold key at any time
+    Vault,
+}
+
+impl Template {
+    /// Source code and example arguments of the template.
+    fn files(self) -> (&'static str, &'static str) {
+        match self {
+            Template::P2pk => (
+                include_str!("../../templates/p2pk/main.simf"),
+                include_str!("../../templates/p2pk/args.json"),
+            ),
+            Template::Htlc => (
+                include_str!("../../templates/htlc/main.simf"),
+                include_str!("../../templates/htlc/args.json"),
+            ),
+            Template::Multisig => (
+                include_str!("../../templates/multisig/main.simf"),
+                include_str!("../../templates/multisig/args.json"),
+            ),
+            Template::Timelock => (
+                include_str!("../../templates/timelock/main.simf"),
+                include_str!("../../templates/timelock/args.json"),
+            ),
+            Template::Vault => (
+                include_str!("../../templates/vault/main.simf"),
+                include_str!("../../templates/vault/args.json"),
+            ),
+        }
+    }
+}
+
+const GITIGNORE: &str = "target/\n";
+
+pub fn new(args: NewArgs) -> Result<()> {
+    if args.path.exists() {
+        anyhow::bail!("Destination {} already exists", args.path.display());
+    }
+    create_project(&args.path, args.template)
+}
+
+pub fn init(args: InitArgs) -> Result<()> {
+    let source_path = args.path.join("src").join("main.simf");
+    if source_path.exists() {
+        anyhow::bail!(
+            "{} already exists, the project is initialized",
+            source_path.display()
+        );
+    }
+    create_project(&args.path, args.template)
+}
+
+/// Write the project layout expected by the other commands:
+/// `src/main.simf`, `witness.json`, `args.json` and `.gitignore`.
+fn create_project(path: &Path, kind: Template) -> Result<()> {
+    let (source, arguments) = kind.files();
+    // The witness file holds placeholders since valid signatures depend on the spending transaction
+    let interface = program_interface(source).with_context(|| "Failed to analyze template")?;
+    let witness = serde_json::to_string_pretty(&template(&interface.witnesses)?)
+        .with_context(|| "Failed to serialize witness template")?;
+
+    let src_dir = path.join("src");
+    fs::create_dir_all(&src_dir)
+        .with_context(|| format!("Failed to create directory: {}", src_dir.display()))?;
+
+    write_file(&src_dir.join("main.simf"), source, false)?;
+    write_file(&path.join("witness.json"), &witness, false)?;
+    write_file(&path.join("args.json"), arguments, false)?;
+    // Keep an existing .gitignore when initializing an existing directory
+    write_file(&path.join(".gitignore"), GITIGNORE, true)?;
+
+    println!(
+        "Created `{}` project in {}",
+        kind.to_possible_value().unwrap().get_name(),
+        path.display()
+    );
+    println!("Run `simply test` in the project directory to run the template tests");
+    Ok(())
+}
+
+fn write_file(path: &Path, content: &str, keep_existing: bool) -> Result<()> {
+    if path.exists() {
+        if keep_existing {
+            return Ok(());
+        }
+        anyhow::bail!("{} already exists", path.display());
+    }
+    fs::write(path, content).with_context(|| format!("Failed to write file: {}", path.display()))
+}
@@ -0,0 +1,92 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use elements::secp256k1_zkp::{rand::rngs::OsRng, SecretKey, XOnlyPublicKey};
+
+use crate::keystore::{keystore_dir, list_nums, load_nums, nums_key, parse_tweak, store_nums};
+use crate::script::unspendable_key;
+
+#[derive(Subcommand, Debug)]
+pub enum NumsCommand {
+    /// Generate a random NUMS key and store its tweak in the keystore
+    New(NumsNameArgs),
+
+    /// List the stored NUMS keys
+    List,
+
+    /// Print the tweak that proves a stored key is unspendable
+    Prove(NumsNameArgs),
+
+    /// Check that an internal key is unspendable given its tweak
+    Verify(NumsVerifyArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct NumsNameArgs {
+    /// Name of the NUMS key
+    pub name: String,
+}
+
+#[derive(Args, Debug)]
+pub struct NumsVerifyArgs {
+    /// Internal key (x-only, hex)
+    #[arg(long, value_name = "HEX")]
+    pub key: String,
+
+    /// Tweak `r` revealed by `simply nums prove`
+    #[arg(long, value_name = "HEX")]
+    pub tweak: String,
+}
+
+pub fn nums(command: NumsCommand) -> Result<()> {
+    match command {
+        NumsCommand::New(args) => {
+            let dir = keystore_dir()?;
+            // A uniformly random secret key is a valid non-zero scalar
+            let tweak = SecretKey::new(&mut OsRng).into();
+            let path = store_nums(&dir, &args.name, &tweak)?;
+            println!("NUMS key `{}` written to: {}", args.name, path.display());
+            println!("Internal key (x-only): {}", nums_key(&tweak)?);
+            println!("Use it with `--internal-key nums:{}`", args.name);
+            Ok(())
+        }
+        NumsCommand::List => {
+            let dir = keystore_dir()?;
+            let names = list_nums(&dir)?;
+            if names.is_empty() {
+                println!("No NUMS keys in {}", dir.display());
+            }
+            for name in names {
+                println!("{}: {}", name, nums_key(&load_nums(&dir, &name)?)?);
+            }
+            Ok(())
+        }
+        NumsCommand::Prove(args) => {
+            let tweak = load_nums(&keystore_dir()?, &args.name)?;
+            println!("Internal key (x-only): {}", nums_key(&tweak)?);
+            println!("NUMS point H: {}", unspendable_key());
+            println!("Tweak r: {}", hex::encode(tweak.to_be_bytes()));
+            println!(
+                "\nThe internal key is H + rG. Check it with `simply nums verify --key <KEY> --tweak <R>`."
+            );
+            Ok(())
+        }
+        NumsCommand::Verify(args) => {
+            let key = hex::decode(args.key.trim())
+                .ok()
+                .and_then(|bytes| XOnlyPublicKey::from_slice(&bytes).ok())
+                .ok_or_else(|| anyhow!("Invalid x-only public key"))?;
+            let expected = nums_key(&parse_tweak(&args.tweak)?)?;
+            anyhow::ensure!(
+                key == expected,
+                "Key {} is not H + rG for the given tweak (expected {})",
+                key,
+                expected
+            );
+            println!(
+                "OK: {} is H + rG, nobody knows its secret key and the output can only be spent through its scripts",
+                key
+            );
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use base64::Engine;
+use clap::{Args, Subcommand};
+use elements::pset::raw::ProprietaryKey;
+use elements::pset::{Input, PartiallySignedTransaction};
+use elements::{
+    confidential::{Asset, Nonce, Value},
+    encode, AssetIssuance, LockTime, OutPoint, Script, Sequence, Transaction, TxIn, TxInWitness,
+    TxOut, Txid,
+};
+use simfony::CompiledProgram;
+use simplicity::jet::elements::ElementsUtxo;
+
+use crate::{
+    commands::build::{ProgramArgs, TaprootArgs},
+    esplora,
+    helpers::{load_spend_witness, parse_assignment},
+    interface::ProgramInterface,
+    script::{
+        create_script, simplicity_control_block, simplicity_leaf_version, spend_info_address,
+    },
+    transaction::{
+        load_txouts, script_path_witness, tlbtc_asset_id, verify_script_spend, OutputSpec,
+    },
+};
+
+/// Prefix of the proprietary PSET fields written by simply
+const PROPRIETARY_PREFIX: &[u8] = b"simply";
+
+/// Proprietary input field holding the encoded Simplicity program, keyed by its CMR
+const PROGRAM_SUBTYPE: u8 = 0x00;
+
+#[derive(Subcommand, Debug)]
+pub enum PsetCommand {
+    /// Create a PSET with the given inputs and outputs
+    Create(PsetCreateArgs),
+
+    /// Add the spent output and the Simplicity program of an input
+    Update(PsetUpdateArgs),
+
+    /// Satisfy the program of an input and add its final witness
+    Finalize(PsetFinalizeArgs),
+
+    /// Extract the final transaction from a PSET
+    Extract(PsetExtractArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct PsetCreateArgs {
+    /// Output to spend (can be repeated)
+    #[arg(long = "input", value_name = "TXID:VOUT", required = true)]
+    pub inputs: Vec<String>,
+
+    /// Output to create, in satoshis (L-BTC by default, can be repeated)
+    #[arg(long = "output", value_name = "ADDRESS:AMOUNT[:ASSET]")]
+    pub outputs: Vec<OutputSpec>,
+
+    /// Fee in satoshis (L-BTC)
+    #[arg(long)]
+    pub fee: u64,
+
+    /// Path of the PSET file to write
+    #[arg(long, value_name = "PATH")]
+    pub pset: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct PsetUpdateArgs {
+    /// Path of the PSET file, updated in place
+    #[arg(long, value_name = "PATH")]
+    pub pset: PathBuf,
+
+    #[command(flatten)]
+    pub input: PsetInputArgs,
+
+    /// Path to a JSON file with the outputs spent by the PSET, one per input,
+    /// instead of fetching the spent output from the chain backend
+    #[arg(long, value_name = "PATH")]
+    pub utxos: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct PsetFinalizeArgs {
+    /// Path of the PSET file, updated in place
+    #[arg(long, value_name = "PATH")]
+    pub pset: PathBuf,
+
+    #[command(flatten)]
+    pub input: PsetInputArgs,
+
+    /// Path to the witness file
+    #[arg(long)]
+    pub witness: Option<PathBuf>,
+
+    /// Witness value in SimplicityHL syntax, overrides the witness file, can be repeated
+    #[arg(long = "witness-value", value_name = "NAME=VALUE", value_parser = parse_assignment)]
+    pub witness_values: Vec<(String, String)>,
+}
+
+/// Program spending an input of the PSET
+#[derive(Args, Debug)]
+pub struct PsetInputArgs {
+    #[command(flatten)]
+    pub program: ProgramArgs,
+
+    /// Index of the PSET input that spends the program
+    #[arg(long, visible_alias = "input", default_value_t = 0)]
+    pub input_index: u32,
+
+    #[command(flatten)]
+    pub taproot: TaprootArgs,
+}
+
+impl PsetInputArgs {
+    /// Index of the selected input, which must exist in the PSET.
+    fn index(&self, pset: &PartiallySignedTransaction) -> Result<usize> {
+        let index = self.input_index as usize;
+        anyhow::ensure!(
+            index < pset.inputs().len(),
+            "Input index {} is out of range, PSET has {} inputs",
+            index,
+            pset.inputs().len()
+        );
+        Ok(index)
+    }
+
+    /// Declared interface and compiled program.
+    fn compile(&self) -> Result<(ProgramInterface, CompiledProgram)> {
+        let interface = self.program.interface()?;
+        let arguments = self.program.arguments(&interface)?;
+        let program = self.program.compile(arguments, false)?;
+        Ok((interface, program))
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PsetExtractArgs {
+    /// Path of the PSET file
+    #[arg(long, value_name = "PATH")]
+    pub pset: PathBuf,
+
+    /// Broadcast the transaction instead of printing it
+    #[arg(long)]
+    pub broadcast: bool,
+}
+
+/// Read a base64-encoded PSET file.
+pub fn read_pset(path: &Path) -> Result<PartiallySignedTransaction> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read PSET file: {}", path.display()))?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(content.trim())
+        .with_context(|| format!("PSET file is not valid base64: {}", path.display()))?;
+    encode::deserialize(&bytes)
+        .with_context(|| format!("Failed to decode PSET file: {}", path.display()))
+}
+
+/// Write a PSET file, base64-encoded.
+pub fn write_pset(path: &Path, pset: &PartiallySignedTransaction) -> Result<()> {
+    let content = base64::engine::general_purpose::STANDARD.encode(encode::serialize(pset));
+    fs::write(path, content + "\n")
+        .with_context(|| format!("Failed to write PSET file: {}", path.display()))?;
+    println!("PSET written to: {}", path.display());
+    Ok(())
+}
+
+fn parse_outpoint(s: &str) -> Result<OutPoint> {
+    let (txid, vout) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid input `{}`, expected TXID:VOUT", s))?;
+    let txid = Txid::from_str(txid).map_err(|_| anyhow!("Invalid TXID format: {}", txid))?;
+    let vout = vout
+        .parse()
+        .map_err(|_| anyhow!("Invalid output index: {}", vout))?;
+    Ok(OutPoint::new(txid, vout))
+}
+
+fn program_key(cmr: &[u8]) -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: PROPRIETARY_PREFIX.to_vec(),
+        subtype: PROGRAM_SUBTYPE,
+        key: cmr.to_vec(),
+    }
+}
+
+pub fn pset(command: PsetCommand) -> Result<()> {
+    match command {
+        PsetCommand::Create(args) => create(args),
+        PsetCommand::Update(args) => update(args),
+        PsetCommand::Finalize(args) => finalize(args),
+        PsetCommand::Extract(args) => extract(args),
+    }
+}
+
+fn create(args: PsetCreateArgs) -> Result<()> {
+    // Outputs are written explicit, blinding them is not supported
+    for output in &args.outputs {
+        anyhow::ensure!(
+            output.address.blinding_pubkey.is_none(),
+            "Output address {} is confidential, only explicit outputs are supported: use its unconfidential address",
+            output.address
+        );
+    }
+    let inputs = args
+        .inputs
+        .iter()
+        .map(|input| parse_outpoint(input))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut output: Vec<TxOut> = args
+        .outputs
+        .iter()
+        .map(|output| TxOut {
+            value: Value::Explicit(output.amount),
+            script_pubkey: output.address.script_pubkey(),
+            asset: Asset::Explicit(output.asset),
+            nonce: Nonce::Null,
+            witness: Default::default(),
+        })
+        .collect();
+    output.push(TxOut::new_fee(args.fee, tlbtc_asset_id()));
+    let tx = Transaction {
+        version: 2,
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .into_iter()
+            .map(|outpoint| TxIn {
+                previous_output: outpoint,
+                is_pegin: false,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                asset_issuance: AssetIssuance::null(),
+                witness: TxInWitness::empty(),
+            })
+            .collect(),
+        output,
+    };
+
+    write_pset(&args.pset, &PartiallySignedTransaction::from_tx(tx))
+}
+
+fn update(args: PsetUpdateArgs) -> Result<()> {
+    let mut pset = read_pset(&args.pset)?;
+    let index = args.input.index(&pset)?;
+    anyhow::ensure!(
+        !is_finalized(&pset.inputs()[index]),
+        "Input {} is already finalized",
+        index
+    );
+
+    let (_, program) = args.input.compile()?;
+    let spend_info = args.input.taproot.spend_info(&program)?;
+    let script = create_script(&program)?;
+    let control_block = simplicity_control_block(script.clone(), &spend_info)?;
+
+    let input = &mut pset.inputs_mut()[index];
+    let outpoint = OutPoint::new(input.previous_txid, input.previous_output_index);
+    let utxo = match &args.utxos {
+        Some(path) => load_txouts(path)?
+            .into_iter()
+            .nth(index)
+            .ok_or_else(|| anyhow!("UTXO file has no output for input {}", index))?,
+        None => esplora::fetch_output(&outpoint)?,
+    };
+    let address = spend_info_address(&spend_info);
+    anyhow::ensure!(
+        utxo.script_pubkey == address.script_pubkey(),
+        "Input {} does not spend the program output, expected script pubkey of {}",
+        index,
+        address
+    );
+
+    input.witness_utxo = Some(utxo);
+    input.tap_internal_key = Some(spend_info.internal_key());
+    input.tap_merkle_root = spend_info.merkle_root();
+    input
+        .tap_scripts
+        .insert(control_block, (script.clone(), simplicity_leaf_version()));
+    input.proprietary.insert(
+        program_key(script.as_bytes()),
+        program.commit().encode_to_vec(),
+    );
+    println!(
+        "Input {}: program {} (CMR {})",
+        index,
+        args.input.program.entrypoint.display(),
+        program.commit().cmr()
+    );
+    write_pset(&args.pset, &pset)
+}
+
+fn finalize(args: PsetFinalizeArgs) -> Result<()> {
+    let mut pset = read_pset(&args.pset)?;
+    let index = args.input.index(&pset)?;
+    if is_finalized(&pset.inputs()[index]) {
+        println!(
+            "Input {} is already finalized, its witness is replaced",
+            index
+        );
+    }
+
+    let (interface, program) = args.input.compile()?;
+    let witness = load_spend_witness(args.witness.as_ref(), &args.witness_values, &interface)?;
+    let script = create_script(&program)?;
+    anyhow::ensure!(
+        pset.inputs()[index]
+            .proprietary
+            .contains_key(&program_key(script.as_bytes())),
+        "Input {} does not carry the program with CMR {}, run `simply pset update` first",
+        index,
+        program.commit().cmr()
+    );
+    let spend_info = args.input.taproot.spend_info(&program)?;
+
+    // The environment needs the outputs spent by every input
+    let utxos = pset
+        .inputs()
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            input
+                .witness_utxo
+                .clone()
+                .map(ElementsUtxo::from)
+                .ok_or_else(|| anyhow!("Input {} has no spent output (witness UTXO)", i))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let tx = pset
+        .extract_tx()
+        .map_err(|e| anyhow!("Failed to extract transaction from PSET: {}", e))?;
+    let final_script_witness =
+        script_path_witness(&tx, utxos.clone(), index, &spend_info, &program, &witness)?;
+    pset.inputs_mut()[index].final_script_witness = Some(final_script_witness);
+
+    // Signatures of the inputs finalized before commit to the annex (padding) of this one
+    let tx = pset
+        .extract_tx()
+        .map_err(|e| anyhow!("Failed to extract transaction from PSET: {}", e))?;
+    for (i, input) in pset.inputs().iter().enumerate() {
+        let carries_program = input
+            .proprietary
+            .keys()
+            .any(|key| key.prefix == PROPRIETARY_PREFIX && key.subtype == PROGRAM_SUBTYPE);
+        if i != index && carries_program && is_finalized(input) {
+            verify_script_spend(&tx, utxos.clone(), i).with_context(|| {
+                format!(
+                    "Input {} no longer verifies with the padding of input {}, finalize input {} again",
+                    i, index, i
+                )
+            })?;
+        }
+    }
+    println!("Input {} finalized", index);
+    write_pset(&args.pset, &pset)
+}
+
+/// Whether the input has a final witness: a PSET created from a transaction
+/// records an empty one for every input.
+fn is_finalized(input: &Input) -> bool {
+    input
+        .final_script_witness
+        .as_ref()
+        .is_some_and(|witness| !witness.is_empty())
+}
+
+fn extract(args: PsetExtractArgs) -> Result<()> {
+    let pset = read_pset(&args.pset)?;
+    let missing: Vec<String> = pset
+        .inputs()
+        .iter()
+        .enumerate()
+        .filter(|(_, input)| !is_finalized(input))
+        .map(|(i, _)| i.to_string())
+        .collect();
+    anyhow::ensure!(
+        missing.is_empty(),
+        "Inputs are not finalized: {}",
+        missing.join(", ")
+    );
+    let tx = pset
+        .extract_tx()
+        .map_err(|e| anyhow!("Failed to extract transaction from PSET: {}", e))?;
+
+    if args.broadcast {
+        let txid = esplora::broadcast_tx(tx)?;
+        println!("Transaction ID: {}", txid);
+    } else {
+        println!("Transaction hex: {}", encode::serialize_hex(&tx));
+    }
+    Ok(())
+}
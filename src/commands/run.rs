@@ -1,20 +1,23 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::Args;
-use elements::{LockTime, Sequence};
-use simfony::dummy_env;
+use elements::{LockTime, Sequence, Transaction};
+use simfony::{dummy_env, Arguments, CompiledProgram, WitnessValues};
 use simplicity::{
     ffi::tests::{run_program, TestUpTo},
+    jet::elements::ElementsEnv,
     BitMachine,
 };
-use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::{
     commands::{
-        build::{compile_program, satisfy_program},
+        build::{satisfy_program, EnvArgs},
         BuildArgs,
     },
-    helpers::{load_arguments, load_witness},
-    tracker,
+    diagnostics::{failure_report, SourceFile},
+    helpers::load_checked_witness,
+    interface::program_interface,
+    memory, tracker,
 };
 
 #[derive(Args, Debug)]
@@ -22,25 +25,61 @@ pub struct RunArgs {
     #[command(flatten)]
     pub build: BuildArgs,
 
-    /// Path to file with arguments
-    #[arg(long)]
-    pub param: Option<PathBuf>,
-
     /// Print debug logs
     #[arg(long)]
     pub logging: Option<Logging>,
 
-    /// Lock time
-    /// See https://learnmeabitcoin.com/technical/transaction/locktime/
+    #[command(flatten)]
+    pub trace: TraceArgs,
+
+    /// Report peak Bit Machine memory usage against the static bounds
     #[arg(long)]
+    pub memory: bool,
+
+    /// Print the timeline of frame pushes and pops (implies `--memory`)
+    #[arg(long, value_name = "MAX_EVENTS", num_args = 0..=1, default_missing_value = "1000")]
+    pub memory_timeline: Option<usize>,
+
+    /// Lock time of the dummy transaction
+    /// See https://learnmeabitcoin.com/technical/transaction/locktime/
+    #[arg(long, conflicts_with = "tx")]
     pub lock_time: Option<u32>,
 
-    /// Sequence
+    /// Sequence of the dummy transaction input
     /// See https://learnmeabitcoin.com/technical/transaction/input/sequence/
-    #[arg(long)]
+    #[arg(long, conflicts_with = "tx")]
     pub sequence: Option<u32>,
 }
 
+/// Options refining the output of `--logging trace`
+#[derive(Args, Clone, Debug, Default)]
+pub struct TraceArgs {
+    /// Only trace jets matching the pattern (e.g. `sha_256_*`), can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    pub trace_include: Vec<String>,
+
+    /// Do not trace jets matching the pattern, can be repeated
+    #[arg(long, value_name = "PATTERN")]
+    pub trace_exclude: Vec<String>,
+
+    /// Print only a per-jet summary of call counts and cumulative cost
+    #[arg(long)]
+    pub trace_summary: bool,
+
+    /// Maximum number of jet calls to print
+    #[arg(long, value_name = "N")]
+    pub trace_limit: Option<usize>,
+}
+
+impl TraceArgs {
+    fn jet_filter(&self) -> Result<tracker::JetFilter> {
+        let mut filter = tracker::JetFilter::new(&self.trace_include, &self.trace_exclude)?;
+        filter.summary_only = self.trace_summary;
+        filter.limit = self.trace_limit;
+        Ok(filter)
+    }
+}
+
 #[derive(clap::ValueEnum, Clone, PartialEq, PartialOrd, Debug)]
 pub enum Logging {
     #[clap(name = "info")]
@@ -58,42 +97,76 @@ pub fn run(args: RunArgs) -> Result<()> {
 }
 
 pub(crate) fn run_inner(args: RunArgs) -> Result<String> {
-    let witness = if let Some(witness_path) = args.build.witness {
-        load_witness(Some(&witness_path))?
-    } else {
-        Default::default()
-    };
+    let program = &args.build.program;
+    let source_file = SourceFile::new(program.entrypoint.display().to_string(), program.source()?);
 
-    let arguments = if let Some(param_path) = args.param {
-        load_arguments(Some(&param_path))?
-    } else {
-        Default::default()
-    };
-
-    let compiled = compile_program(
-        &args.build.entrypoint,
-        arguments,
-        args.logging.is_some(),
-        args.build.mcpp_inc_path,
+    // Check the files against the program before compiling to report all problems at once
+    let interface = program_interface(source_file.text()).with_context(|| {
+        format!(
+            "Failed to analyze program: {}",
+            program.entrypoint.display()
+        )
+    })?;
+    let witness = load_checked_witness(
+        args.build.witness.as_ref(),
+        &args.build.witness_values,
+        &interface,
     )?;
-    let satisfied = satisfy_program(compiled, witness, args.build.prune)?;
-    let node = satisfied.redeem();
-    let env = dummy_env::dummy_with(
-        LockTime::from_consensus(args.lock_time.unwrap_or(0)),
-        Sequence::from_consensus(args.sequence.unwrap_or(0)),
-        false,
-    );
+    let arguments = program.arguments(&interface)?;
+
+    let memory = args.memory || args.memory_timeline.is_some();
+
+    let res = if args.logging.is_some() || memory {
+        let logging = args.logging;
+        // Debug symbols change the CMR, the environment commits to the program as deployed
+        let deployed = CompiledProgram::new(source_file.text(), arguments.clone(), false)
+            .map_err(|e| anyhow::anyhow!(e))
+            .with_context(|| "Failed to compile program")?;
+        let env = run_env(&args.build.env, args.lock_time, args.sequence, &deployed)?;
+        let compiled = CompiledProgram::new(source_file.text(), arguments.clone(), true)
+            .map_err(|e| anyhow::anyhow!(e))
+            .with_context(|| "Failed to compile program with debug symbols")?;
+        let satisfied = satisfy_program(compiled, witness.clone(), args.build.prune, Some(&env))?;
+        let node = satisfied.redeem();
 
-    let res = if let Some(logging) = args.logging {
         let mut machine = BitMachine::for_program(node)?;
-        let mut tracker = tracker::Tracker {
-            debug_symbols: satisfied.debug_symbols(),
-            debug_logs: logging >= Logging::Debug,
-            jet_traces: logging == Logging::Trace,
-        };
-        let res = machine.exec_with_tracker(node, &env, &mut tracker)?;
-        format!("Result: {}", res)
+        let mut tracker = tracker::Tracker::new(satisfied.debug_symbols());
+        tracker.debug_logs = logging >= Some(Logging::Debug);
+        tracker.jet_traces = logging == Some(Logging::Trace);
+        tracker.jet_filter = args.trace.jet_filter()?;
+
+        let res = machine.exec_with_tracker(node, &env, &mut tracker);
+        if tracker.jet_traces && (args.trace.trace_summary || args.trace.trace_limit.is_some()) {
+            println!("{}", tracker.jet_summary());
+        }
+        if memory {
+            // A failed measurement must not hide the result or the failure report
+            if let Err(e) = report_memory(
+                deployed,
+                witness,
+                args.build.prune,
+                &env,
+                args.memory_timeline,
+            ) {
+                println!("warning: failed to measure memory usage: {:#}", e);
+            }
+        }
+        match res {
+            Ok(res) => format!("Result: {}", res),
+            Err(e) => anyhow::bail!(
+                "Program exited with error: {}\n\n{}",
+                e,
+                tracker_failure_report(&source_file, &e.to_string(), &arguments, &tracker)
+            ),
+        }
     } else {
+        let compiled = CompiledProgram::new(source_file.text(), arguments.clone(), false)
+            .map_err(|e| anyhow::anyhow!(e))
+            .with_context(|| "Failed to compile program")?;
+        let env = run_env(&args.build.env, args.lock_time, args.sequence, &compiled)?;
+        let satisfied = satisfy_program(compiled, witness.clone(), args.build.prune, Some(&env))?;
+        let node = satisfied.redeem();
+
         let (program_bytes, witness_bytes) = node.encode_to_vec();
         let output = run_program(
             &program_bytes,
@@ -103,12 +176,111 @@ pub(crate) fn run_inner(args: RunArgs) -> Result<String> {
             Some(env.c_tx_env()),
         )
         .map_err(|e| anyhow::anyhow!("Failed to run program: {}", e))?;
-        output
-            .eval_result
-            .into_result()
-            .map_err(|e| anyhow::anyhow!("Program exited with error: {}", e))?;
+        if let Err(e) = output.eval_result.into_result() {
+            let report = explain_failure(&source_file, arguments, witness, args.build.prune, &env)
+                .unwrap_or_else(|err| format!("Failed to explain the failure: {:#}", err));
+            anyhow::bail!("Program exited with error: {}\n\n{}", e, report);
+        }
         format!("Cost bound: {}", output.cost_bound)
     };
 
     Ok(res)
 }
+
+/// Environment to run the program in: the spending transaction if one is provided,
+/// otherwise a dummy transaction with the given lock time and sequence.
+fn run_env(
+    env_args: &EnvArgs,
+    lock_time: Option<u32>,
+    sequence: Option<u32>,
+    compiled: &CompiledProgram,
+) -> Result<ElementsEnv<Arc<Transaction>>> {
+    if let Some(env) = env_args.spend_env(compiled)? {
+        return Ok(env);
+    }
+    Ok(dummy_env::dummy_with(
+        LockTime::from_consensus(lock_time.unwrap_or(0)),
+        Sequence::from_consensus(sequence.unwrap_or(0)),
+        false,
+    ))
+}
+
+/// Execute the program as deployed, without debug symbols, and print its memory usage
+/// against its static bounds.
+fn report_memory(
+    deployed: CompiledProgram,
+    witness: WitnessValues,
+    prune: bool,
+    env: &ElementsEnv<Arc<Transaction>>,
+    timeline: Option<usize>,
+) -> Result<()> {
+    let satisfied = satisfy_program(deployed, witness, prune, Some(env))?;
+    let node = satisfied.redeem();
+
+    let mut machine = BitMachine::for_program(node)?;
+    let mut tracker = tracker::Tracker::new(satisfied.debug_symbols());
+    tracker.trace = Some(Vec::new());
+    // A failure is part of the trace, the report covers the memory used until then
+    let _ = machine.exec_with_tracker(node, env, &mut tracker);
+
+    let trace = tracker.trace.unwrap_or_default();
+    let usage = memory::replay(node, &trace)
+        .with_context(|| "Replay of the execution diverged from the program")?;
+    println!("{}", usage.report(&node.bounds()));
+    if let Some(limit) = timeline {
+        println!("{}", usage.format_timeline(limit));
+    }
+    Ok(())
+}
+
+/// Render a failure report from the state collected by the tracker.
+fn tracker_failure_report(
+    source: &SourceFile,
+    error: &str,
+    arguments: &Arguments,
+    tracker: &tracker::Tracker,
+) -> String {
+    failure_report(
+        source,
+        error,
+        arguments,
+        tracker.debug_symbols,
+        tracker.last_fallible_call.as_ref(),
+        &tracker.recent_calls.iter().copied().collect::<Vec<_>>(),
+        &tracker
+            .recent_dbg_values
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Re-execute a failed program with debug symbols in the Bit Machine
+/// to map the failure back to the source.
+fn explain_failure(
+    source: &SourceFile,
+    arguments: Arguments,
+    witness: WitnessValues,
+    prune: bool,
+    env: &ElementsEnv<Arc<Transaction>>,
+) -> Result<String> {
+    let compiled = CompiledProgram::new(source.text(), arguments.clone(), true)
+        .map_err(|e| anyhow::anyhow!(e))
+        .with_context(|| "Failed to compile program with debug symbols")?;
+    let satisfied = satisfy_program(compiled, witness, prune, Some(env))?;
+    let node = satisfied.redeem();
+
+    let mut machine = BitMachine::for_program(node)?;
+    let mut tracker = tracker::Tracker::new(satisfied.debug_symbols());
+    match machine.exec_with_tracker(node, env, &mut tracker) {
+        Ok(_) => Ok("The Bit Machine executed the program successfully, \
+            the failure is specific to the consensus evaluator."
+            .to_string()),
+        Err(e) => Ok(tracker_failure_report(
+            source,
+            &e.to_string(),
+            &arguments,
+            &tracker,
+        )),
+    }
+}
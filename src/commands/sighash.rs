@@ -0,0 +1,50 @@
+use anyhow::Result;
+use clap::Args;
+
+use crate::{
+    commands::build::{EnvArgs, ProgramArgs},
+    transaction::sig_all_hash,
+};
+
+#[derive(Args, Debug)]
+pub struct SighashArgs {
+    #[command(flatten)]
+    pub program: ProgramArgs,
+
+    /// The spending transaction and its UTXOs are required
+    #[command(flatten)]
+    pub env: EnvArgs,
+}
+
+pub fn sighash(args: SighashArgs) -> Result<()> {
+    let interface = args.program.interface()?;
+    let arguments = args.program.arguments(&interface)?;
+    let program = args.program.compile(arguments, false)?;
+
+    // The hash commits to the program leaf, so it is computed for the program's own input
+    let env = args.env.spend_env(&program)?.ok_or_else(|| {
+        anyhow::anyhow!("The spending transaction is required, pass --tx and --utxos")
+    })?;
+    let message = sig_all_hash(&env);
+
+    println!("Input: {}", args.env.input_index);
+    println!("CMR: {}", program.commit().cmr());
+    match env.annex() {
+        Some(annex) => println!("Annex (padding): {} bytes", annex.len()),
+        None => println!("Annex (padding): none"),
+    }
+    println!("Sighash (sig_all_hash): {}", message);
+    if env.annex().is_none() {
+        // The padding is only known once the program is satisfied, after signing
+        println!(
+            "\nWARNING: the hash commits to the annex (padding) of the input, which is read from its witness in the transaction. \
+            If the spend needs padding, the hash changes once it is added: name a key in the witness file instead, \
+            so that `withdraw`, `batch` or `pset finalize` sign the final transaction."
+        );
+    }
+    println!(
+        "\nSign it with `simply sign --message {}` and put the signature in the witness",
+        message
+    );
+    Ok(())
+}
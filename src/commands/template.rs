@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{
+    commands::build::read_source,
+    interface::{program_interface, template},
+};
+
+#[derive(Subcommand, Debug)]
+pub enum TemplateCommand {
+    /// Write a template file with the names and types declared by the program
+    Init(TemplateInitArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TemplateInitArgs {
+    /// Path to the source file
+    /// Default: `./src/main.simf`
+    #[arg(long, default_value = "./src/main.simf")]
+    pub entrypoint: PathBuf,
+
+    /// Path to the mcpp include directory
+    #[arg(long)]
+    pub mcpp_inc_path: Option<PathBuf>,
+
+    /// Path to the output file (`./witness.json` or `./args.json` by default)
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+
+    /// Overwrite the output file if it exists
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Kind of values a template is generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateKind {
+    Witness,
+    Arguments,
+}
+
+pub fn witness(command: TemplateCommand) -> Result<()> {
+    match command {
+        TemplateCommand::Init(args) => init(args, TemplateKind::Witness),
+    }
+}
+
+pub fn arguments(command: TemplateCommand) -> Result<()> {
+    match command {
+        TemplateCommand::Init(args) => init(args, TemplateKind::Arguments),
+    }
+}
+
+fn init(args: TemplateInitArgs, kind: TemplateKind) -> Result<()> {
+    let source = read_source(&args.entrypoint, args.mcpp_inc_path)?;
+    let interface = program_interface(&source)
+        .with_context(|| format!("Failed to analyze program: {}", args.entrypoint.display()))?;
+
+    let (entries, default_output, what) = match kind {
+        TemplateKind::Witness => (&interface.witnesses, "./witness.json", "witness"),
+        TemplateKind::Arguments => (&interface.parameters, "./args.json", "parameter"),
+    };
+    let output = args.output.unwrap_or_else(|| PathBuf::from(default_output));
+    if output.exists() && !args.force {
+        anyhow::bail!(
+            "{} already exists, use --force to overwrite it",
+            output.display()
+        );
+    }
+
+    let content = serde_json::to_string_pretty(&template(entries)?)
+        .with_context(|| "Failed to serialize template")?;
+    fs::write(&output, content)
+        .with_context(|| format!("Failed to write file: {}", output.display()))?;
+
+    for (name, ty) in entries {
+        println!("  {}: {}", name, ty);
+    }
+    println!(
+        "Template with {} {}(s) written to: {}",
+        entries.len(),
+        what,
+        output.display()
+    );
+    println!("Replace the zero values with actual ones before use");
+    Ok(())
+}
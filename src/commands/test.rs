@@ -1,12 +1,13 @@
 use anyhow::{Context, Result};
 use clap::Args;
 use regex::Regex;
-use std::path::PathBuf;
 use std::{fs, path::Path};
-use walkdir::WalkDir;
 
+use crate::commands::build::ProgramArgs;
 use crate::commands::run::run_inner;
-use crate::commands::{BuildArgs, Logging, RunArgs};
+use crate::commands::{BuildArgs, Logging, RunArgs, TraceArgs};
+use crate::helpers::find_simf_files;
+use crate::interface::program_interface;
 
 // Colors for output
 const GREEN: &str = "\x1b[0;32m";
@@ -21,6 +22,9 @@ pub struct TestArgs {
     /// Print debug logs (info, debug, trace)
     #[arg(long, value_enum)]
     pub logging: Option<Logging>,
+
+    #[command(flatten)]
+    pub trace: TraceArgs,
 }
 
 #[derive(Debug)]
@@ -37,7 +41,7 @@ pub fn test(args: TestArgs) -> Result<()> {
     let mut passed_tests = 0;
 
     // Find all *.simf files recursively in current directory
-    let source_dir = args.build.entrypoint.parent().unwrap();
+    let source_dir = args.build.program.entrypoint.parent().unwrap();
     let simf_files = find_simf_files(source_dir.to_str().unwrap())?;
 
     for file_path in simf_files {
@@ -76,7 +80,7 @@ pub fn test(args: TestArgs) -> Result<()> {
             if let Some(error) = &result.error_message {
                 println!("{}", error);
             }
-            println!("");
+            println!();
         }
 
         println!(
@@ -144,32 +148,88 @@ fn remove_function_by_name(source: &str, name: &str) -> String {
     output
 }
 
-fn find_simf_files(dir: &str) -> Result<Vec<PathBuf>> {
-    let mut files = Vec::new();
-    for entry in WalkDir::new(dir) {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("simf") {
-            files.push(path.to_path_buf());
-        }
-    }
-    Ok(files)
-}
-
 fn extract_test_functions(file_path: &Path) -> Result<Vec<String>> {
     let content = fs::read_to_string(file_path)
         .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
+    Ok(test_functions(&content))
+}
 
-    let test_regex = Regex::new(r"fn (test_[a-zA-Z0-9_]*)").unwrap();
+/// Names of the test functions: `test_*` functions without parameters or return value.
+fn test_functions(content: &str) -> Vec<String> {
+    let test_regex = Regex::new(r"fn (test_[a-zA-Z0-9_]*)\s*\(\s*\)\s*\{").unwrap();
     let mut test_functions = Vec::new();
 
-    for cap in test_regex.captures_iter(&content) {
+    for cap in test_regex.captures_iter(content) {
         if let Some(test_func) = cap.get(1) {
             test_functions.push(test_func.as_str().to_string());
         }
     }
 
-    Ok(test_functions)
+    test_functions
+}
+
+/// Keep only the arguments declared by the test program.
+///
+/// The argument file is shared by all tests, while each test only uses some of the parameters.
+fn restrict_arguments(program: &mut ProgramArgs, source: &str, temp_dir: &Path) -> Result<()> {
+    // Let the run report programs that cannot be analyzed
+    let Ok(interface) = program_interface(source) else {
+        return Ok(());
+    };
+    program
+        .arg_values
+        .retain(|(name, _)| interface.parameters.contains_key(name));
+
+    if let Some(param_path) = &program.param {
+        let content = fs::read_to_string(param_path)
+            .with_context(|| format!("Failed to read argument file: {}", param_path.display()))?;
+        let mut arguments: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse argument file: {}", param_path.display()))?;
+        if let Some(entries) = arguments.as_object_mut() {
+            entries.retain(|name, _| interface.parameters.contains_key(name));
+        }
+
+        let temp_param = temp_dir.join("args.json");
+        fs::write(&temp_param, arguments.to_string())
+            .with_context(|| format!("Failed to write temp file: {}", temp_param.display()))?;
+        program.param = Some(temp_param);
+    }
+    Ok(())
+}
+
+/// Source of the program running a test: the test function becomes `main`.
+///
+/// The other tests are removed, since the program declares the parameters of all its functions.
+/// Tests that are still called by the remaining code are kept.
+fn test_program(content: &str, test_func: &str) -> String {
+    // If file already defines a `main`, remove the whole function to avoid conflicts
+    let mut content = remove_function_by_name(content, "main");
+    // Removing a test can leave the functions it calls unused, so repeat until nothing changes
+    let mut removed = true;
+    while removed {
+        removed = false;
+        for other in test_functions(&content) {
+            if other == test_func {
+                continue;
+            }
+            let without = remove_function_by_name(&content, &other);
+            if !is_called(&without, &other) {
+                content = without;
+                removed = true;
+            }
+        }
+    }
+
+    // Replace the specific test function declaration with `main`
+    let test_decl_re =
+        Regex::new(&format!(r"(?m)^\s*fn\s+{}\b", regex::escape(test_func))).unwrap();
+    test_decl_re.replace(&content, "fn main").to_string()
+}
+
+/// Whether the source calls the function `name`.
+fn is_called(source: &str, name: &str) -> bool {
+    let call_re = Regex::new(&format!(r"\b{}\s*\(", regex::escape(name))).unwrap();
+    call_re.is_match(source)
 }
 
 fn run_single_test(
@@ -187,29 +247,29 @@ fn run_single_test(
     // Read original file content
     let content = fs::read_to_string(file_path)
         .with_context(|| format!("Failed to read file: {}", file_path.display()))?;
-
-    // If file already defines a `main`, remove the whole function to avoid conflicts
-    let content = remove_function_by_name(&content, "main");
-
-    // Replace the specific test function declaration with `main`
-    let test_decl_re =
-        Regex::new(&format!(r"(?m)^\s*fn\s+{}\b", regex::escape(test_func))).unwrap();
-    let modified_content = test_decl_re.replace(&content, "fn main").to_string();
+    let modified_content = test_program(&content, test_func);
 
     // Write modified content to temp file
-    fs::write(&temp_file, modified_content)
+    fs::write(&temp_file, &modified_content)
         .with_context(|| format!("Failed to write temp file: {}", temp_file.display()))?;
 
     // Create RunArgs for the test
     let mut run_args = RunArgs {
         build: args.build.clone(),
-        param: None,
         logging: args.logging.clone(),
+        trace: args.trace.clone(),
+        memory: false,
+        memory_timeline: None,
         lock_time: None,
         sequence: None,
     };
     // Update the build path to use the temporary file
-    run_args.build.entrypoint = temp_file;
+    run_args.build.program.entrypoint = temp_file;
+    restrict_arguments(
+        &mut run_args.build.program,
+        &modified_content,
+        temp_dir.path(),
+    )?;
 
     // Call run function directly
     match run_inner(run_args) {
@@ -228,3 +288,133 @@ fn run_single_test(
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const SOURCE: &str = r#"fn test_min_height() {
+    jet::check_lock_height(param::MIN_HEIGHT);
+}
+
+fn test_owner() {
+    let pk: Pubkey = param::OWNER;
+    assert!(jet::eq_256(pk, pk));
+}
+
+fn main() {
+    jet::check_lock_height(param::MIN_HEIGHT);
+}
+"#;
+
+    fn program_args(param: Option<PathBuf>, arg_values: &[(&str, &str)]) -> ProgramArgs {
+        ProgramArgs {
+            entrypoint: PathBuf::from("tests.simf"),
+            mcpp_inc_path: None,
+            param,
+            arg_values: arg_values
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_restrict_arguments_per_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let param_path = dir.path().join("args.json");
+        fs::write(
+            &param_path,
+            r#"{
+                "MIN_HEIGHT": {"value": "1000", "type": "u32"},
+                "OWNER": {"value": "0x79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798", "type": "Pubkey"},
+                "UNUSED": {"value": "1", "type": "u8"}
+            }"#,
+        )
+        .unwrap();
+        let program = program_args(
+            Some(param_path),
+            &[("MIN_HEIGHT", "2000"), ("OWNER", "0x01"), ("UNUSED", "2")],
+        );
+
+        for (test_func, expected) in [("test_min_height", "MIN_HEIGHT"), ("test_owner", "OWNER")] {
+            let source = test_program(SOURCE, test_func);
+            let temp_dir = tempfile::tempdir().unwrap();
+            let mut restricted = program.clone();
+            restrict_arguments(&mut restricted, &source, temp_dir.path()).unwrap();
+
+            let names: Vec<&str> = restricted
+                .arg_values
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect();
+            assert_eq!(names, [expected], "{}", test_func);
+
+            let param_path = restricted.param.unwrap();
+            assert!(param_path.starts_with(temp_dir.path()));
+            let arguments: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(param_path).unwrap()).unwrap();
+            let names: Vec<&String> = arguments.as_object().unwrap().keys().collect();
+            assert_eq!(names, [expected], "{}", test_func);
+        }
+    }
+
+    #[test]
+    fn test_restrict_arguments_unanalyzed_program() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut program = program_args(None, &[("MIN_HEIGHT", "2000")]);
+        // The run reports the error, the arguments are kept as given
+        restrict_arguments(&mut program, "fn main() {", temp_dir.path()).unwrap();
+        assert_eq!(program.arg_values.len(), 1);
+        assert_eq!(program.param, None);
+    }
+
+    #[test]
+    fn test_program_keeps_helpers() {
+        let source = r#"fn test_value() -> u32 {
+    42
+}
+
+fn test_check(x: u32) {
+    assert!(jet::eq_32(x, 42));
+}
+
+fn test_answer() {
+    test_check(test_value());
+}
+
+fn test_setup() {
+    assert!(jet::eq_32(test_value(), 42));
+}
+
+fn test_other() {
+    test_setup();
+}
+
+fn test_unrelated() {
+    assert!(jet::eq_32(1, 1));
+}
+"#;
+        // Helpers with parameters or a return value are not tests
+        assert_eq!(
+            test_functions(source),
+            ["test_answer", "test_setup", "test_other", "test_unrelated"]
+        );
+
+        let program = test_program(source, "test_answer");
+        assert!(program.contains("fn test_value()"));
+        assert!(program.contains("fn test_check(x: u32)"));
+        assert!(program.contains("fn main()"));
+        assert!(!program.contains("fn test_answer"));
+        assert!(!program.contains("fn test_other"));
+        assert!(!program.contains("fn test_unrelated"));
+        // Only called by a removed test
+        assert!(!program.contains("fn test_setup"));
+
+        // Tests called by the running test are kept
+        let program = test_program(source, "test_other");
+        assert!(program.contains("fn test_setup()"));
+        assert!(!program.contains("fn test_answer"));
+    }
+}
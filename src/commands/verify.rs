@@ -0,0 +1,284 @@
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use clap::Args;
+use elements::{Address, Script};
+use simfony::CompiledProgram;
+
+use crate::{
+    commands::build::{
+        load_artifacts_from, load_taptree, read_source, BuildMetadata, ProgramArgs, TaprootArgs,
+        COMPILER_REVISION,
+    },
+    helpers::{check_arguments_json, from_json, get_program_name},
+    interface::program_interface,
+    script::{create_script, spend_info_address},
+    taptree::LeafRecord,
+};
+
+/// Program settings that are not given are taken from the recorded build
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub program: ProgramArgs,
+
+    #[command(flatten)]
+    pub taproot: TaprootArgs,
+
+    /// Expected P2TR address
+    #[arg(long, required_unless_present = "cmr", conflicts_with = "cmr")]
+    pub address: Option<String>,
+
+    /// Expected CMR (hex)
+    #[arg(long)]
+    pub cmr: Option<String>,
+
+    /// Build artifact with the recorded build inputs
+    /// Default: `<target-dir>/<program name>.json`
+    #[arg(long)]
+    pub artifact: Option<PathBuf>,
+
+    /// Directory with the build artifacts (will use `target` by default)
+    #[arg(long, name = "target-dir", default_value = "./target")]
+    pub target_dir: PathBuf,
+}
+
+pub fn verify(args: VerifyArgs) -> Result<()> {
+    let recorded = load_recorded_metadata(&args)?;
+    if let Some(metadata) = &recorded {
+        // Another compiler can produce another program even from identical inputs
+        if metadata.compiler_revision != COMPILER_REVISION {
+            println!(
+                "WARNING: the program was built with compiler revision {}, the current one is {}",
+                metadata.compiler_revision, COMPILER_REVISION
+            );
+        }
+    }
+
+    // Explicit settings take precedence over the recorded ones
+    let program = &args.program;
+    let mcpp_inc_path = program
+        .mcpp_inc_path
+        .clone()
+        .or_else(|| recorded.as_ref().and_then(|m| m.mcpp_inc_path.clone()));
+    let source = read_source(&program.entrypoint, mcpp_inc_path.clone())?;
+    let interface = program_interface(&source).with_context(|| {
+        format!(
+            "Failed to analyze program: {}",
+            program.entrypoint.display()
+        )
+    })?;
+    let (arguments_json, arguments_source) = match (&program.param, &recorded) {
+        (Some(param), _) => {
+            let content = fs::read_to_string(param)
+                .with_context(|| format!("Failed to read argument file: {}", param.display()))?;
+            let json = serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse argument file: {}", param.display()))?;
+            (json, param.display().to_string())
+        }
+        (None, Some(metadata)) => (
+            metadata.arguments.clone(),
+            "<recorded arguments>".to_string(),
+        ),
+        (None, None) => (serde_json::json!({}), "<no argument file>".to_string()),
+    };
+    let arguments_json = check_arguments_json(
+        arguments_json,
+        &arguments_source,
+        &program.arg_values,
+        &interface,
+    )?;
+    let arguments = from_json(&arguments_json).with_context(|| "Failed to parse arguments")?;
+    let recorded_taproot = match &recorded {
+        Some(metadata) => metadata.taproot_args()?,
+        None => TaprootArgs::default(),
+    };
+    let taproot = TaprootArgs {
+        internal_key: args
+            .taproot
+            .internal_key
+            .clone()
+            .or(recorded_taproot.internal_key.clone()),
+        ..Default::default()
+    };
+
+    let compiled = CompiledProgram::new(source.as_str(), arguments, false)
+        .map_err(|e| anyhow!(e))
+        .with_context(|| "Failed to compile program")?;
+    let cmr = compiled.commit().cmr();
+    let script = create_script(&compiled)?;
+    // The other leaves of the recorded tree are kept, the program takes the place of its leaf
+    let taptree = match (&args.taproot.taptree, &recorded) {
+        (Some(path), _) => Some(load_taptree(path)?),
+        (None, Some(metadata)) => metadata.taptree(Some(&script))?,
+        (None, None) => None,
+    };
+    let address = spend_info_address(&taproot.leaves_spend_info(script, taptree.as_deref())?);
+    let current = BuildMetadata::new(
+        &source,
+        mcpp_inc_path,
+        arguments_json,
+        cmr,
+        taproot.internal_key.as_ref(),
+        taptree.as_deref(),
+    )?;
+
+    println!(
+        "Source: {} (sha256: {})",
+        program.entrypoint.display(),
+        current.source_sha256
+    );
+    println!("Compiler revision: {}", current.compiler_revision);
+    println!("CMR: {}", cmr);
+    println!("Address: {}", address);
+
+    let (matches, recorded_matches, expected) = if let Some(expected_cmr) = &args.cmr {
+        let expected_cmr = expected_cmr.trim().trim_start_matches("0x").to_lowercase();
+        (
+            expected_cmr == cmr.to_string(),
+            recorded.as_ref().map(|m| m.cmr == expected_cmr),
+            format!("CMR {}", expected_cmr),
+        )
+    } else {
+        let expected_address = args.address.as_deref().unwrap_or_default();
+        let expected =
+            Address::from_str(expected_address).map_err(|_| anyhow!("Invalid address format"))?;
+        let recorded_matches = recorded.as_ref().map(|metadata| {
+            recorded_address(metadata, &recorded_taproot)
+                .is_ok_and(|address| address.script_pubkey() == expected.script_pubkey())
+        });
+        (
+            address.script_pubkey() == expected.script_pubkey(),
+            recorded_matches,
+            format!("address {}", expected_address),
+        )
+    };
+
+    if matches {
+        println!("\nOK: {} commits to the program", expected);
+        return Ok(());
+    }
+
+    println!("\nMISMATCH: {} does not commit to the program", expected);
+    match (&recorded, recorded_matches) {
+        (None, _) => println!(
+            "No build metadata recorded, build the program to record its inputs and compare them"
+        ),
+        (Some(recorded), Some(true)) => {
+            println!("The recorded build matches, the following inputs differ:");
+            print_differences(recorded, &current);
+        }
+        (Some(recorded), _) => {
+            println!("The recorded build does not match either (e.g. the address was derived with other taproot settings)");
+            print_differences(recorded, &current);
+        }
+    }
+    anyhow::bail!("Verification failed")
+}
+
+/// Load the build metadata from the artifact, if there is one.
+fn load_recorded_metadata(args: &VerifyArgs) -> Result<Option<BuildMetadata>> {
+    let artifact = match &args.artifact {
+        Some(artifact) => artifact.clone(),
+        None => {
+            let program_name = get_program_name(&args.program.entrypoint)?;
+            let artifact = args.target_dir.join(program_name).with_extension("json");
+            if !artifact.exists() {
+                return Ok(None);
+            }
+            artifact
+        }
+    };
+    let metadata = load_artifacts_from(&artifact)?.metadata;
+    if metadata.is_none() {
+        println!(
+            "WARNING: {} has no build metadata, using default settings",
+            artifact.display()
+        );
+    }
+    Ok(metadata)
+}
+
+/// Address of the recorded build: the recorded CMR in the recorded taproot tree.
+///
+/// Fails if the recorded taptree does not have the recorded leaf.
+fn recorded_address(metadata: &BuildMetadata, taproot: &TaprootArgs) -> Result<Address> {
+    let cmr = hex::decode(&metadata.cmr).with_context(|| "Recorded CMR is not valid hex")?;
+    anyhow::ensure!(cmr.len() == 32, "Recorded CMR must be 32 bytes");
+    let taptree = metadata.taptree(None)?;
+    let spend_info = taproot.leaves_spend_info(Script::from(cmr), taptree.as_deref())?;
+    Ok(spend_info_address(&spend_info))
+}
+
+fn print_differences(recorded: &BuildMetadata, current: &BuildMetadata) {
+    let fields = [
+        (
+            "simply version",
+            recorded.simply_version.clone(),
+            current.simply_version.clone(),
+        ),
+        (
+            "compiler revision",
+            recorded.compiler_revision.clone(),
+            current.compiler_revision.clone(),
+        ),
+        (
+            "mcpp include path",
+            format!("{:?}", recorded.mcpp_inc_path),
+            format!("{:?}", current.mcpp_inc_path),
+        ),
+        (
+            "source sha256",
+            recorded.source_sha256.clone(),
+            current.source_sha256.clone(),
+        ),
+        (
+            "arguments",
+            recorded.arguments.to_string(),
+            current.arguments.to_string(),
+        ),
+        ("CMR", recorded.cmr.clone(), current.cmr.clone()),
+        (
+            "internal key",
+            format!("{:?}", recorded.internal_key),
+            format!("{:?}", current.internal_key),
+        ),
+        (
+            "taptree",
+            format_taptree(recorded.taptree.as_deref()),
+            format_taptree(current.taptree.as_deref()),
+        ),
+    ];
+
+    let mut any = false;
+    for (name, recorded, current) in fields {
+        if recorded != current {
+            any = true;
+            println!(
+                "  {}:\n    recorded: {}\n    current:  {}",
+                name, recorded, current
+            );
+        }
+    }
+    if !any {
+        println!("  none, the current build is identical to the recorded one");
+    }
+}
+
+/// Leaves of a recorded taptree, one per line.
+fn format_taptree(leaves: Option<&[LeafRecord]>) -> String {
+    let Some(leaves) = leaves else {
+        return "none".to_string();
+    };
+    leaves
+        .iter()
+        .map(|leaf| {
+            format!(
+                "\n      {:?}, version 0x{:02x}: {}",
+                leaf.placement, leaf.version, leaf.script
+            )
+        })
+        .collect()
+}
@@ -1,71 +1,169 @@
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Result};
 use clap::Args;
-use elements::{Address, Txid};
+use elements::secp256k1_zkp::SecretKey;
+use elements::{Address, Transaction, Txid};
 
 use crate::{
-    commands::{build::compile_program, BuildArgs},
+    commands::build::{ProgramArgs, TaprootArgs},
     esplora,
-    helpers::load_witness,
-    script::{create_p2tr_address, unspendable_key},
-    transaction::spend_script_path,
+    helpers::{load_spend_witness, parse_assignment},
+    keystore::{keystore_dir, load_keypair},
+    script::spend_info_address,
+    transaction::{
+        load_outputs, spend, unblind_utxo, OutputSpec, Outputs, SpendInput, SpendPath, MIN_FEE_RATE,
+    },
 };
 
 #[derive(Args, Debug)]
 pub struct WithdrawArgs {
     #[command(flatten)]
-    pub build: BuildArgs,
+    pub program: ProgramArgs,
+
+    /// Path to the witness file
+    #[arg(long)]
+    pub witness: Option<PathBuf>,
+
+    /// Witness value in SimplicityHL syntax, overrides the witness file, can be repeated
+    #[arg(long = "witness-value", value_name = "NAME=VALUE", value_parser = parse_assignment)]
+    pub witness_values: Vec<(String, String)>,
+
+    #[command(flatten)]
+    pub taproot: TaprootArgs,
 
     /// Transaction ID to spend
     #[arg(long)]
     pub txid: String,
 
-    /// Destination address
+    /// Keystore key that blinds the deposit address, to unblind a confidential UTXO
+    #[arg(long, value_name = "NAME")]
+    pub blinding_key: Option<String>,
+
+    /// Spend with the internal key (--internal-key) instead of the program
+    #[arg(long, requires = "internal_key")]
+    pub key_path: bool,
+
+    #[command(flatten)]
+    pub payment: PaymentArgs,
+}
+
+/// Outputs, fee and broadcast of a spending transaction
+#[derive(Args, Debug)]
+pub struct PaymentArgs {
+    /// Destination address, receives the change: everything left after the outputs and the fee.
+    /// Outputs are blinded if their address is confidential
     #[arg(long)]
     pub destination: String,
 
+    /// Output to create, in satoshis (L-BTC by default, can be repeated)
+    #[arg(long = "output", value_name = "ADDRESS:AMOUNT[:ASSET]")]
+    pub outputs: Vec<OutputSpec>,
+
+    /// Path to a JSON file with outputs to create, added after the `--output` ones
+    #[arg(long, value_name = "PATH")]
+    pub outputs_file: Option<PathBuf>,
+
+    /// Fee rate in sat/vB, estimated by the chain backend by default
+    #[arg(long, value_name = "SAT/VB")]
+    pub fee_rate: Option<f64>,
+
     /// Dry run
     #[arg(long)]
     pub dry_run: bool,
 }
 
+impl PaymentArgs {
+    /// Outputs of the transaction, with the change sent to the destination.
+    pub fn outputs(&self) -> Result<Outputs> {
+        let destination = Address::from_str(&self.destination)
+            .map_err(|_| anyhow!("Invalid destination address format"))?;
+        let mut outputs = self.outputs.clone();
+        if let Some(path) = &self.outputs_file {
+            outputs.extend(load_outputs(path)?);
+        }
+        Ok(Outputs {
+            outputs,
+            change: destination,
+        })
+    }
+
+    /// Fee rate given on the command line, or estimated by the chain backend.
+    pub fn fee_rate(&self) -> Result<f64> {
+        match self.fee_rate {
+            Some(fee_rate) => Ok(fee_rate),
+            None => Ok(esplora::fee_rate_estimate()?
+                .unwrap_or(MIN_FEE_RATE)
+                .max(MIN_FEE_RATE)),
+        }
+    }
+
+    /// Broadcast the transaction, or print it on a dry run.
+    pub fn send(&self, tx: Transaction) -> Result<()> {
+        if !self.dry_run {
+            let txid = esplora::broadcast_tx(tx)?;
+            println!("Transaction ID: {}", txid);
+        } else {
+            println!("Transaction hex: {}", elements::encode::serialize_hex(&tx));
+        }
+        Ok(())
+    }
+}
+
+/// Load a blinding key from the keystore, to unblind confidential UTXOs.
+pub fn load_blinding_key(name: Option<&String>) -> Result<Option<SecretKey>> {
+    match name {
+        Some(name) => Ok(Some(load_keypair(&keystore_dir()?, name)?.secret_key())),
+        None => Ok(None),
+    }
+}
+
 pub fn withdraw(args: WithdrawArgs) -> Result<()> {
-    let program = compile_program(
-        &args.build.entrypoint,
-        Default::default(),
-        false,
-        args.build.mcpp_inc_path,
-    )?;
-    let address = create_p2tr_address(program.clone(), unspendable_key())?;
+    let interface = args.program.interface()?;
+    // Fail early on invalid arguments or an invalid witness, before fetching anything
+    let arguments = args.program.arguments(&interface)?;
+    // Spending with the key needs the secret key instead of the witness
+    let keypair = if args.key_path {
+        let internal_key = args.taproot.internal_key.as_ref();
+        Some(internal_key.expect("required by --key-path").keypair()?)
+    } else {
+        None
+    };
+    let witness = match keypair {
+        Some(_) => None,
+        None => Some(load_spend_witness(
+            args.witness.as_ref(),
+            &args.witness_values,
+            &interface,
+        )?),
+    };
+    let outputs = args.payment.outputs()?;
+
+    let program = args.program.compile(arguments, false)?;
+    let spend_info = args.taproot.spend_info(&program)?;
+    let address = spend_info_address(&spend_info);
+    let blinding_key = load_blinding_key(args.blinding_key.as_ref())?;
 
     let txid: Txid = Txid::from_str(&args.txid).map_err(|_| anyhow!("Invalid TXID format"))?;
     let (outpoint, utxo) = esplora::fetch_utxo(&txid, &address)?;
+    let utxo_secrets = unblind_utxo(&utxo, blinding_key.as_ref())?;
+    let fee_rate = args.payment.fee_rate()?;
 
-    let destination = Address::from_str(&args.destination)
-        .map_err(|_| anyhow!("Invalid destination address format"))?;
-
-    // Create and sign transaction using the transaction module
-    let witness = if let Some(witness_path) = args.build.witness {
-        load_witness(Some(&witness_path))?
-    } else {
-        Default::default()
+    let path = match (keypair, witness) {
+        (Some(keypair), _) => SpendPath::Key(keypair),
+        (None, Some(witness)) => SpendPath::Script { program, witness },
+        (None, None) => unreachable!("either a key or a witness is loaded"),
     };
-    let tx = spend_script_path(
+    let input = SpendInput {
         outpoint,
         utxo,
-        destination,
-        unspendable_key(),
-        program,
-        witness,
-    )?;
-
-    if !args.dry_run {
-        let txid = esplora::broadcast_tx(tx)?;
-        println!("Transaction ID: {}", txid);
-    } else {
-        println!("Transaction hex: {}", elements::encode::serialize_hex(&tx));
-    }
+        secrets: utxo_secrets,
+        spend_info,
+        path,
+    };
 
-    Ok(())
+    // Create and sign transaction using the transaction module
+    let tx = spend(&[input], &outputs, fee_rate)?;
+    args.payment.send(tx)
 }
@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the project configuration file
+pub const CONFIG_FILE_NAME: &str = "Simply.toml";
+
+/// Project configuration, loaded from `Simply.toml`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub fmt: FmtConfig,
+}
+
+/// Formatter settings (`[fmt]` section)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FmtConfig {
+    /// Number of spaces per indentation level
+    pub indent_width: usize,
+    /// Indent with tabs instead of spaces
+    pub hard_tabs: bool,
+    /// Maximum number of consecutive blank lines to keep
+    pub max_blank_lines: usize,
+}
+
+impl Default for FmtConfig {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            hard_tabs: false,
+            max_blank_lines: 1,
+        }
+    }
+}
+
+/// Find the project configuration file, starting from `dir` and walking up the tree.
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .map(|ancestor| ancestor.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Load the project configuration, falling back to defaults if there is none.
+pub fn load_project_config(dir: &Path) -> Result<ProjectConfig> {
+    let Some(path) = find_project_config(dir) else {
+        return Ok(ProjectConfig::default());
+    };
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Failed to parse config file: {}", path.display()))
+}
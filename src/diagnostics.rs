@@ -0,0 +1,542 @@
+use regex::Regex;
+use simfony::debug::{DebugSymbols, TrackedCallName};
+use simfony::error::Span;
+use simfony::parse::{self, ParseFromStr};
+use simfony::{Arguments, CompiledProgram};
+use simplicity::dag::{DagLike, InternalSharing};
+use simplicity::node::Inner;
+use simplicity::Cmr;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// SimplicityHL source with helpers to map tracked expressions back to their location.
+pub struct SourceFile {
+    name: String,
+    text: String,
+}
+
+/// Function definition found in the source.
+#[derive(Debug, Clone)]
+pub struct FunctionSpan {
+    pub name: String,
+    /// Byte range of the whole definition, including the body.
+    pub span: Range<usize>,
+    /// Names of the functions that the body calls
+    pub calls: Vec<String>,
+}
+
+/// Call that the compiler tracks with a debug symbol: jets, `assert!`, `panic!`, `dbg!` and unwraps.
+#[derive(Debug, Clone)]
+pub struct SourceCall {
+    pub name: parse::CallName,
+    /// Byte range of the call expression.
+    pub span: Range<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Parse the source into a parse tree.
+    pub fn parse(&self) -> anyhow::Result<parse::Program> {
+        parse::Program::parse_from_str(&self.text).map_err(|e| anyhow::anyhow!("{}", e))
+    }
+
+    /// Byte range of a span of the parse tree.
+    pub fn range(&self, span: &Span) -> Range<usize> {
+        let offset = |line: usize, column: usize| {
+            let line_start: usize = self
+                .text
+                .split_inclusive('\n')
+                .take(line - 1)
+                .map(str::len)
+                .sum();
+            let rest = &self.text[line_start.min(self.text.len())..];
+            line_start
+                + rest
+                    .char_indices()
+                    .nth(column - 1)
+                    .map_or(rest.len(), |(i, _)| i)
+        };
+        offset(span.start.line.get(), span.start.col.get())
+            ..offset(span.end.line.get(), span.end.col.get())
+    }
+
+    /// Tracked calls of the program, keyed by the CMR of their debug symbol.
+    ///
+    /// The source is compiled once more with a numbered comment before the closing parenthesis
+    /// of every call. Comments don't change the program, so its debug symbols have the same CMRs,
+    /// and the last marker in the text of a debug symbol names its call. Calls whose text differs
+    /// from the original debug symbol are left out.
+    pub fn tracked_calls(
+        &self,
+        arguments: &Arguments,
+        debug_symbols: &DebugSymbols,
+    ) -> HashMap<Cmr, SourceCall> {
+        let Ok(program) = self.parse() else {
+            return HashMap::new();
+        };
+        let mut calls = Vec::new();
+        for item in program.items() {
+            if let parse::Item::Function(function) = item {
+                visit_calls(function.body(), &mut |call| calls.push(call));
+            }
+        }
+
+        let mut closing_parens: Vec<(usize, usize)> = calls
+            .iter()
+            .enumerate()
+            .filter_map(|(index, call)| {
+                let end = self.range(call.as_ref()).end;
+                self.text[..end].ends_with(')').then_some((end - 1, index))
+            })
+            .collect();
+        closing_parens.sort_unstable_by(|a, b| b.cmp(a));
+        let mut marked = self.text.clone();
+        for (offset, index) in closing_parens {
+            marked.insert_str(offset, &format!("/*{}*/", index));
+        }
+        let Ok(marked_program) = CompiledProgram::new(marked, arguments.clone(), true) else {
+            return HashMap::new();
+        };
+
+        let marker = Regex::new(r"/\*(\d+)\*/").expect("valid regex");
+        marked_program
+            .commit()
+            .post_order_iter::<InternalSharing>()
+            .filter_map(|data| match data.node.inner() {
+                Inner::AssertL(_, cmr) => Some(*cmr),
+                _ => None,
+            })
+            .filter_map(|cmr| {
+                let marked_text = marked_program.debug_symbols().get(&cmr)?.text();
+                let index: usize = marker.captures_iter(marked_text).last()?[1].parse().ok()?;
+                let tracked = debug_symbols.get(&cmr)?;
+                (tracked.text() == marker.replace_all(marked_text, "")).then(|| {
+                    let call = calls[index];
+                    let span = self.range(call.as_ref());
+                    (
+                        cmr,
+                        SourceCall {
+                            name: call.name().clone(),
+                            span,
+                        },
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Convert a byte offset into a 1-based (line, column) pair.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let prefix = &self.text[..offset.min(self.text.len())];
+        let line = prefix.matches('\n').count() + 1;
+        let line_start = prefix.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (line, prefix[line_start..].chars().count() + 1)
+    }
+
+    /// List all top-level function definitions.
+    pub fn functions(&self) -> Vec<FunctionSpan> {
+        let Ok(program) = self.parse() else {
+            return vec![];
+        };
+        program
+            .items()
+            .iter()
+            .filter_map(|item| match item {
+                parse::Item::Function(function) => Some(function),
+                _ => None,
+            })
+            .map(|function| {
+                let mut calls = Vec::new();
+                visit_calls(function.body(), &mut |call| match call.name() {
+                    parse::CallName::Custom(name)
+                    | parse::CallName::Fold(name, _)
+                    | parse::CallName::ForWhile(name) => calls.push(name.to_string()),
+                    _ => {}
+                });
+                FunctionSpan {
+                    name: function.name().to_string(),
+                    span: self.range(function.as_ref()),
+                    calls,
+                }
+            })
+            .collect()
+    }
+
+    /// Name of the function that contains the given byte offset.
+    pub fn enclosing_function(&self, offset: usize) -> Option<String> {
+        self.functions()
+            .into_iter()
+            .find(|f| f.span.contains(&offset))
+            .map(|f| f.name)
+    }
+
+    /// Reconstruct the chain of calls that leads from `main` to `function`.
+    ///
+    /// The chain is derived from the call sites in the parse tree. When a function has
+    /// several callers, the one that was active most recently (according to `recent`,
+    /// a list of function names ordered from oldest to newest) is preferred.
+    pub fn call_chain(&self, function: &str, recent: &[String]) -> Vec<String> {
+        let functions = self.functions();
+        let mut chain = vec![function.to_string()];
+
+        while chain.last().map(String::as_str) != Some("main") {
+            let callee = chain.last().unwrap().clone();
+            let callers: Vec<&FunctionSpan> = functions
+                .iter()
+                .filter(|f| f.name != callee && !chain.contains(&f.name))
+                .filter(|f| f.calls.contains(&callee))
+                .collect();
+
+            let caller = recent
+                .iter()
+                .rev()
+                .find_map(|name| callers.iter().find(|f| &f.name == name))
+                .or_else(|| callers.first());
+
+            match caller {
+                Some(caller) => chain.push(caller.name.clone()),
+                None => break,
+            }
+        }
+
+        chain
+    }
+
+    /// Render a rustc-style diagnostic pointing at `span`.
+    pub fn render(
+        &self,
+        level: &str,
+        message: &str,
+        span: Option<Range<usize>>,
+        notes: &[String],
+    ) -> String {
+        let mut out = format!("{}: {}\n", level, message);
+
+        let Some(span) = span else {
+            out.push_str(&format!("  --> {}\n", self.name));
+            for note in notes {
+                out.push_str(&format!("   = note: {}\n", note));
+            }
+            return out;
+        };
+
+        let (line, column) = self.location(span.start);
+        let line_text = self.text.lines().nth(line - 1).unwrap_or_default();
+        let gutter = line.to_string().len();
+        let pad = " ".repeat(gutter);
+
+        // Only underline the part of the span that fits on the first line
+        let underline_len = span
+            .len()
+            .min(line_text.len().saturating_sub(column - 1))
+            .max(1);
+
+        out.push_str(&format!("{}--> {}:{}:{}\n", pad, self.name, line, column));
+        out.push_str(&format!("{} |\n", pad));
+        out.push_str(&format!("{} | {}\n", line, line_text));
+        out.push_str(&format!(
+            "{} | {}{}\n",
+            pad,
+            " ".repeat(column - 1),
+            "^".repeat(underline_len)
+        ));
+        if !notes.is_empty() {
+            out.push_str(&format!("{} |\n", pad));
+        }
+        for note in notes {
+            out.push_str(&format!("{} = note: {}\n", pad, note));
+        }
+        out
+    }
+}
+
+/// Visit the calls of an expression, including the calls in their arguments.
+fn visit_calls<'a, F>(expression: &'a parse::Expression, visit: &mut F)
+where
+    F: FnMut(&'a parse::Call),
+{
+    use parse::SingleExpressionInner as S;
+
+    let single = match expression.inner() {
+        parse::ExpressionInner::Single(single) => single,
+        parse::ExpressionInner::Block(statements, last) => {
+            for statement in statements.iter() {
+                match statement {
+                    parse::Statement::Assignment(assignment) => {
+                        visit_calls(assignment.expression(), visit)
+                    }
+                    parse::Statement::Expression(expression) => visit_calls(expression, visit),
+                }
+            }
+            if let Some(last) = last {
+                visit_calls(last, visit);
+            }
+            return;
+        }
+    };
+    match single.inner() {
+        S::Call(call) => {
+            visit(call);
+            for arg in call.args() {
+                visit_calls(arg, visit);
+            }
+        }
+        S::Match(match_) => {
+            visit_calls(match_.scrutinee(), visit);
+            visit_calls(match_.left().expression(), visit);
+            visit_calls(match_.right().expression(), visit);
+        }
+        S::Either(either) => visit_calls(either.as_ref().either(|e| e, |e| e), visit),
+        S::Option(Some(inner)) | S::Expression(inner) => visit_calls(inner, visit),
+        S::Tuple(elements) | S::Array(elements) | S::List(elements) => {
+            for element in elements.iter() {
+                visit_calls(element, visit);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Describe the failure of a tracked call.
+fn describe_failure(name: &TrackedCallName) -> &'static str {
+    match name {
+        TrackedCallName::Assert => "assertion failed",
+        TrackedCallName::Panic => "explicit panic",
+        TrackedCallName::UnwrapLeft(_) | TrackedCallName::UnwrapRight(_) => {
+            "unwrapped the wrong variant of an Either"
+        }
+        TrackedCallName::Unwrap => "called `unwrap` on a `None` value",
+        TrackedCallName::Jet => "jet failed",
+        TrackedCallName::Debug(_) => "expression failed",
+    }
+}
+
+/// Build a source-mapped report for a failed program execution.
+///
+/// Calls are identified by the CMRs of their debug symbols, which locate them exactly
+/// even if the same expression appears several times in the source.
+pub fn failure_report(
+    source: &SourceFile,
+    error: &str,
+    arguments: &Arguments,
+    debug_symbols: &DebugSymbols,
+    failed_call: Option<&Cmr>,
+    recent_calls: &[Cmr],
+    recent_dbg_values: &[(String, String)],
+) -> String {
+    let Some((cmr, tracked)) =
+        failed_call.and_then(|cmr| debug_symbols.get(cmr).map(|tracked| (cmr, tracked)))
+    else {
+        return source.render(
+            "error",
+            &format!("program failed: {}", error),
+            None,
+            &["no tracked expression was reached before the failure".to_string()],
+        );
+    };
+
+    let calls = source.tracked_calls(arguments, debug_symbols);
+    let span = calls.get(cmr).map(|call| call.span.clone());
+    let mut notes = vec![format!("execution error: {}", error)];
+    if span.is_none() {
+        notes.push(format!("failed call: `{}`", tracked.text()));
+    }
+
+    if let Some(function) = span
+        .as_ref()
+        .and_then(|span| source.enclosing_function(span.start))
+    {
+        // Map recent calls to the functions that contain them to disambiguate call sites
+        let recent_functions: Vec<String> = recent_calls
+            .iter()
+            .filter_map(|cmr| calls.get(cmr))
+            .filter_map(|call| source.enclosing_function(call.span.start))
+            .collect();
+        let chain = source.call_chain(&function, &recent_functions);
+        notes.push(format!(
+            "in {}",
+            chain
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", called from ")
+        ));
+    }
+
+    if !recent_dbg_values.is_empty() {
+        let values = recent_dbg_values
+            .iter()
+            .map(|(text, value)| format!("{} = {}", text, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        notes.push(format!("last debug values: {}", values));
+    }
+
+    source.render("error", describe_failure(tracked.name()), span, &notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::Tracker;
+    use simfony::{dummy_env, Arguments, CompiledProgram, WitnessValues};
+    use simplicity::BitMachine;
+
+    const PROGRAM: &str = r#"// fn commented() { assert!(jet::eq_32(x, 1)) }
+fn first(x: u32) {
+    assert!(jet::eq_32(x, 1))
+}
+
+fn second(x: u32) {
+    assert!(jet::eq_32(x, 1))
+}
+
+fn main() {
+    let x: u32 = witness::X;
+    let (carry, sum): (bool, u32) = jet::add_32(
+        x,
+        dbg!(1)
+    );
+    first(x);
+    second(sum);
+}
+"#;
+
+    fn source() -> SourceFile {
+        SourceFile::new("test.simf", PROGRAM)
+    }
+
+    #[test]
+    fn test_functions() {
+        let functions = source().functions();
+        let names: Vec<_> = functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["first", "second", "main"]);
+        assert_eq!(functions[2].calls, ["first", "second"]);
+        assert!(PROGRAM[functions[0].span.clone()].starts_with("fn first"));
+        assert_eq!(source().call_chain("second", &[]), ["second", "main"]);
+    }
+
+    #[test]
+    fn test_tracked_calls() {
+        let compiled = CompiledProgram::new(PROGRAM, Arguments::default(), true).unwrap();
+        let source = source();
+        let calls = source.tracked_calls(&Arguments::default(), compiled.debug_symbols());
+
+        let mut located: Vec<_> = calls
+            .values()
+            .map(|call| {
+                (
+                    source.location(call.span.start),
+                    &PROGRAM[call.span.clone()],
+                )
+            })
+            .collect();
+        located.sort();
+        assert_eq!(
+            located,
+            [
+                ((3, 5), "assert!(jet::eq_32(x, 1))"),
+                ((3, 13), "jet::eq_32(x, 1)"),
+                ((7, 5), "assert!(jet::eq_32(x, 1))"),
+                ((7, 13), "jet::eq_32(x, 1)"),
+                ((12, 37), "jet::add_32(\n        x,\n        dbg!(1)\n    )"),
+                ((14, 9), "dbg!(1)"),
+            ]
+        );
+    }
+
+    /// Execute a program and report its failure.
+    fn run_failure_report(source: &SourceFile, witness: &str) -> String {
+        let compiled = CompiledProgram::new(source.text(), Arguments::default(), true).unwrap();
+        let witness: WitnessValues = serde_json::from_str(witness).unwrap();
+        let satisfied = compiled.satisfy(witness).unwrap();
+        let node = satisfied.redeem();
+
+        let mut machine = BitMachine::for_program(node).unwrap();
+        let mut tracker = Tracker::new(satisfied.debug_symbols());
+        let error = machine
+            .exec_with_tracker(node, &dummy_env::dummy(), &mut tracker)
+            .unwrap_err();
+        failure_report(
+            source,
+            &error.to_string(),
+            &Arguments::default(),
+            tracker.debug_symbols,
+            tracker.last_fallible_call.as_ref(),
+            &tracker.recent_calls.iter().copied().collect::<Vec<_>>(),
+            &tracker
+                .recent_dbg_values
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn test_failure_report() {
+        let report = run_failure_report(&source(), r#"{"X": {"value": "1", "type": "u32"}}"#);
+
+        // The second of the identical assertions failed
+        assert!(
+            report.starts_with("error: assertion failed\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains(" --> test.simf:7:5\n"), "{}", report);
+        assert!(
+            report.contains("in `second`, called from `main`"),
+            "{}",
+            report
+        );
+        assert!(report.contains("last debug values: 1 = 1"), "{}", report);
+    }
+
+    #[test]
+    fn test_failure_report_identical_calls() {
+        let source = SourceFile::new(
+            "test.simf",
+            r#"fn main() {
+    let x: u32 = 1;
+    assert!(jet::eq_32(x, 1));
+    let x: u32 = witness::X;
+    assert!(jet::eq_32(x, 1));
+}
+"#,
+        );
+        let compiled = CompiledProgram::new(source.text(), Arguments::default(), true).unwrap();
+        let calls = source.tracked_calls(&Arguments::default(), compiled.debug_symbols());
+        let mut lines: Vec<_> = calls
+            .values()
+            .map(|call| source.location(call.span.start))
+            .collect();
+        lines.sort();
+        assert_eq!(lines, [(3, 5), (3, 13), (5, 5), (5, 13)]);
+
+        let report = run_failure_report(&source, r#"{"X": {"value": "2", "type": "u32"}}"#);
+        assert!(report.contains(" --> test.simf:5:5\n"), "{}", report);
+    }
+
+    #[test]
+    fn test_failure_report_without_call() {
+        let report = failure_report(
+            &source(),
+            "failed",
+            &Arguments::default(),
+            &DebugSymbols::default(),
+            None,
+            &[],
+            &[],
+        );
+        assert!(report.contains("no tracked expression was reached"));
+    }
+}
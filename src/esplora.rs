@@ -1,14 +1,32 @@
 use elements::{encode, Address, OutPoint, Transaction, TxOut, Txid};
 
-/// Fetch UTXO given the txid and vout
-pub fn fetch_utxo(txid: &Txid, address: &Address) -> anyhow::Result<(OutPoint, TxOut)> {
-    let url = format!(
-        "https://blockstream.info/liquidtestnet/api/tx/{}/hex",
-        txid.to_string()
-    );
+/// Fetch a transaction given its txid
+pub fn fetch_tx(txid: &Txid) -> anyhow::Result<Transaction> {
+    let url = format!("https://blockstream.info/liquidtestnet/api/tx/{}/hex", txid);
     let tx_hex = reqwest::blocking::get(&url)?.text()?;
     let tx_bytes = hex::decode(tx_hex.trim())?;
-    let transaction: Transaction = encode::deserialize(&tx_bytes)?;
+    Ok(encode::deserialize(&tx_bytes)?)
+}
+
+/// Fetch the output spent by an outpoint
+pub fn fetch_output(outpoint: &OutPoint) -> anyhow::Result<TxOut> {
+    let transaction = fetch_tx(&outpoint.txid)?;
+    transaction
+        .output
+        .get(outpoint.vout as usize)
+        .cloned()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "No output {} in transaction {}",
+                outpoint.vout,
+                outpoint.txid
+            )
+        })
+}
+
+/// Fetch UTXO given the txid and vout
+pub fn fetch_utxo(txid: &Txid, address: &Address) -> anyhow::Result<(OutPoint, TxOut)> {
+    let transaction = fetch_tx(txid)?;
     for (vout, output) in transaction.output.iter().enumerate() {
         if output.script_pubkey == address.script_pubkey() {
             return Ok((OutPoint::new(*txid, vout as u32), output.clone()));
@@ -17,6 +35,17 @@ pub fn fetch_utxo(txid: &Txid, address: &Address) -> anyhow::Result<(OutPoint, T
     Err(anyhow::anyhow!("No UTXO found for address: {}", address))
 }
 
+/// Fee rate (sat/vB) estimated for confirmation in the next block.
+///
+/// Returns `None` if the backend has no estimate, e.g. when blocks are not full.
+pub fn fee_rate_estimate() -> anyhow::Result<Option<f64>> {
+    let url = "https://blockstream.info/liquidtestnet/api/fee-estimates";
+    // Map of confirmation targets (in blocks) to fee rates
+    let estimates: std::collections::HashMap<String, f64> =
+        serde_json::from_str(&reqwest::blocking::get(url)?.text()?)?;
+    Ok(estimates.get("1").copied())
+}
+
 /// Broadcast a transaction to the network
 /// Returns the txid of the broadcasted transaction
 #[allow(dead_code)]
@@ -0,0 +1,230 @@
+use crate::config::FmtConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Identifier, keyword or number literal
+    Word,
+    Punct,
+    LineComment,
+    BlockComment,
+    /// Preprocessor directive (e.g. `#include`), kept verbatim
+    Directive,
+}
+
+#[derive(Debug, Clone)]
+struct Token<'a> {
+    kind: Kind,
+    text: &'a str,
+    /// Number of line breaks between the previous token and this one
+    newlines_before: usize,
+}
+
+const KEYWORDS: &[&str] = &["fn", "let", "match", "type", "mod", "const"];
+
+const MULTI_CHAR_PUNCTS: &[&str] = &["->", "=>", "::"];
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut newlines = 0;
+    let mut at_line_start = true;
+    let mut pos = 0;
+
+    while pos < source.len() {
+        let rest = &source[pos..];
+        let c = rest.chars().next().unwrap();
+
+        if c.is_whitespace() {
+            if c == '\n' {
+                newlines += 1;
+                at_line_start = true;
+            }
+            pos += c.len_utf8();
+            continue;
+        }
+
+        let (kind, len) = if rest.starts_with("//") {
+            (Kind::LineComment, rest.find('\n').unwrap_or(rest.len()))
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            let len = body.find("*/").map(|i| i + 4).unwrap_or(rest.len());
+            (Kind::BlockComment, len)
+        } else if c == '#' && at_line_start {
+            (Kind::Directive, rest.find('\n').unwrap_or(rest.len()))
+        } else if is_word_char(c) {
+            (
+                Kind::Word,
+                rest.find(|c| !is_word_char(c)).unwrap_or(rest.len()),
+            )
+        } else if let Some(punct) = MULTI_CHAR_PUNCTS.iter().find(|p| rest.starts_with(*p)) {
+            (Kind::Punct, punct.len())
+        } else {
+            (Kind::Punct, c.len_utf8())
+        };
+
+        tokens.push(Token {
+            kind,
+            text: rest[..len].trim_end(),
+            newlines_before: newlines,
+        });
+        newlines = 0;
+        at_line_start = false;
+        pos += len;
+    }
+
+    tokens
+}
+
+/// Check whether a space is needed between two tokens on the same line.
+fn needs_space(prev: &Token, next: &Token) -> bool {
+    let is_keyword = |t: &Token| t.kind == Kind::Word && KEYWORDS.contains(&t.text);
+
+    match next.text {
+        "," | ";" | ")" | "]" | ">" | "::" | "." => return false,
+        ":" if next.kind == Kind::Punct => return false,
+        "!" if prev.kind == Kind::Word => return false,
+        "(" | "<" if prev.kind == Kind::Word && !is_keyword(prev) => return false,
+        "(" | "[" if prev.text == "!" => return false,
+        _ => {}
+    }
+    !matches!(prev.text, "(" | "[" | "<" | "!" | "::" | ".") || prev.kind != Kind::Punct
+}
+
+struct Printer<'c> {
+    config: &'c FmtConfig,
+    out: String,
+    indent: usize,
+    at_line_start: bool,
+}
+
+impl<'c> Printer<'c> {
+    fn newline(&mut self, blank_lines: usize) {
+        if !self.at_line_start {
+            self.out.push('\n');
+        }
+        for _ in 0..blank_lines.min(self.config.max_blank_lines) {
+            self.out.push('\n');
+        }
+        self.at_line_start = true;
+    }
+
+    fn write(&mut self, text: &str, space: bool) {
+        if self.at_line_start {
+            if self.config.hard_tabs {
+                self.out.push_str(&"\t".repeat(self.indent));
+            } else {
+                self.out
+                    .push_str(&" ".repeat(self.indent * self.config.indent_width));
+            }
+        } else if space {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.at_line_start = false;
+    }
+}
+
+/// Format SimplicityHL source code.
+///
+/// The formatter works on the token stream: it normalizes indentation and spacing,
+/// puts every statement, match arm and item on its own line and keeps comments and
+/// (a bounded number of) blank lines. Formatting is idempotent.
+pub fn format_source(source: &str, config: &FmtConfig) -> String {
+    let tokens = tokenize(source);
+    let mut printer = Printer {
+        config,
+        out: String::with_capacity(source.len()),
+        indent: 0,
+        at_line_start: true,
+    };
+    // Stack of open brackets, statements and arms are separated only inside blocks
+    let mut brackets: Vec<&str> = Vec::new();
+    let mut break_line = false;
+    let mut prev: Option<&Token> = None;
+
+    for token in &tokens {
+        let in_block = matches!(brackets.last(), None | Some(&"{"));
+        let blank_lines = token.newlines_before.saturating_sub(1);
+
+        match token.kind {
+            Kind::Directive => {
+                printer.newline(if prev.is_some() { blank_lines } else { 0 });
+                let indent = std::mem::take(&mut printer.indent);
+                printer.write(token.text, false);
+                printer.indent = indent;
+                printer.newline(0);
+                break_line = false;
+            }
+            Kind::LineComment => {
+                if prev.is_some() && token.newlines_before == 0 {
+                    printer.write(token.text, true);
+                } else {
+                    printer.newline(if prev.is_some() { blank_lines } else { 0 });
+                    printer.write(token.text, false);
+                }
+                printer.newline(0);
+                break_line = false;
+            }
+            Kind::BlockComment => {
+                if prev.is_some() && token.newlines_before == 0 {
+                    printer.write(token.text, true);
+                } else {
+                    printer.newline(if prev.is_some() { blank_lines } else { 0 });
+                    printer.write(token.text, false);
+                    break_line = true;
+                }
+            }
+            _ => {
+                let is_close = token.text == "}" && token.kind == Kind::Punct;
+                if is_close {
+                    brackets.pop();
+                    printer.indent = printer.indent.saturating_sub(1);
+                }
+
+                let after_open = prev.is_some_and(|p| p.text == "{");
+                if is_close && after_open {
+                    printer.write(token.text, false);
+                } else if is_close
+                    // Tokens following a line comment or directive are already on a new line
+                    || ((break_line || (printer.at_line_start && prev.is_some()))
+                        && !matches!(token.text, "," | ";" | ")" | "]" | ">"))
+                {
+                    let blank_lines = if after_open || is_close {
+                        0
+                    } else {
+                        blank_lines
+                    };
+                    printer.newline(blank_lines);
+                    printer.write(token.text, false);
+                } else {
+                    let space = prev.map(|p| needs_space(p, token)).unwrap_or(false);
+                    printer.write(token.text, space);
+                }
+
+                break_line = false;
+                if token.kind == Kind::Punct {
+                    match token.text {
+                        "{" => {
+                            brackets.push("{");
+                            printer.indent += 1;
+                            break_line = true;
+                        }
+                        "(" | "[" | "<" => brackets.push(token.text),
+                        ")" | "]" | ">" => {
+                            brackets.pop();
+                        }
+                        "}" => break_line = true,
+                        ";" | "," if in_block => break_line = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        prev = Some(token);
+    }
+
+    printer.newline(0);
+    printer.out
+}
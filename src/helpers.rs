@@ -1,39 +1,251 @@
 use anyhow::{Context, Result};
+use elements::secp256k1_zkp::{self as secp256k1, Keypair, Message};
+use serde::de::DeserializeOwned;
+use simfony::parse::ParseFromStr;
+use simfony::types::ResolvedType;
 use simfony::{Arguments, WitnessValues};
-use std::{fs, path::PathBuf};
-
-/// Load witness from a JSON file.
-pub fn load_witness(path: Option<&PathBuf>) -> Result<WitnessValues> {
-    if let Some(path) = path {
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read witness file: {}", path.display()))?;
-        serde_json::from_str(&content).with_context(|| "Failed to parse witness")
-    } else {
-        Ok(WitnessValues::default())
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+use crate::interface::{check_entries, ProgramInterface};
+use crate::keystore::{load_key_file, KeyRef};
+
+/// Load a witness or argument file and check it against the declared entries.
+///
+/// A missing file is treated as an empty one, so that missing entries are reported.
+/// Inline values (in SimplicityHL syntax) are added to the file entries, replacing
+/// entries with the same name.
+fn load_checked<T: DeserializeOwned>(
+    path: Option<&PathBuf>,
+    inline: &[(String, String)],
+    expected: &BTreeMap<String, ResolvedType>,
+    kind: &str,
+) -> Result<T> {
+    let (json, file_name) = read_json(path, kind)?;
+    check_json(json, &file_name, inline, expected, kind)
+}
+
+/// Parse a witness or argument file, an empty object if there is none.
+fn read_json(path: Option<&PathBuf>, kind: &str) -> Result<(serde_json::Value, String)> {
+    let (content, file_name) = match path {
+        Some(path) => (
+            fs::read_to_string(path)
+                .with_context(|| format!("Failed to read {} file: {}", kind, path.display()))?,
+            path.display().to_string(),
+        ),
+        None => ("{}".to_string(), format!("<no {} file>", kind)),
+    };
+    let json = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {} file: {}", kind, file_name))?;
+    Ok((json, file_name))
+}
+
+/// Merge inline values into parsed file content and check the result, see [`load_checked`].
+fn check_json<T: DeserializeOwned>(
+    mut json: serde_json::Value,
+    file_name: &str,
+    inline: &[(String, String)],
+    expected: &BTreeMap<String, ResolvedType>,
+    kind: &str,
+) -> Result<T> {
+    if let Some(entries) = json.as_object_mut() {
+        for (name, value) in inline {
+            // Unknown names keep an empty type and are reported as unexpected
+            let ty = expected
+                .get(name)
+                .map(|ty| ty.to_string())
+                .unwrap_or_default();
+            entries.insert(
+                name.clone(),
+                serde_json::json!({ "value": value, "type": ty }),
+            );
+        }
     }
+
+    let errors = check_entries(&json, expected, kind);
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Invalid {} file: {}\n  {}",
+            kind,
+            file_name,
+            errors.join("\n  ")
+        );
+    }
+    from_json(&json).with_context(|| format!("Failed to parse {} file: {}", kind, file_name))
 }
 
-/// Load arguments from a JSON file.
-pub fn load_arguments(path: Option<&PathBuf>) -> Result<Arguments> {
-    if let Some(path) = path {
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read arguments file: {}", path.display()))?;
-        serde_json::from_str(&content).with_context(|| "Failed to parse arguments")
-    } else {
-        Ok(Arguments::default())
+/// Deserialize witness values or arguments from the JSON file format.
+///
+/// Their deserializers borrow strings from the input, which `serde_json::from_value` can't do.
+pub fn from_json<T: DeserializeOwned>(json: &serde_json::Value) -> serde_json::Result<T> {
+    serde_json::from_str(&json.to_string())
+}
+
+/// Load witness from a JSON file and inline values,
+/// checking it against the witnesses declared by the program.
+pub fn load_checked_witness(
+    path: Option<&PathBuf>,
+    inline: &[(String, String)],
+    interface: &ProgramInterface,
+) -> Result<WitnessValues> {
+    load_checked(path, inline, &interface.witnesses, "witness")
+}
+
+/// Witness of a spend, where `Signature` entries may name a key instead of a value.
+///
+/// Such entries are signed once the spending transaction is final, see [`SpendWitness::fill`].
+#[derive(Debug)]
+pub struct SpendWitness {
+    json: serde_json::Value,
+    file_name: String,
+    inline: Vec<(String, String)>,
+    expected: BTreeMap<String, ResolvedType>,
+    signers: Vec<(String, Keypair)>,
+}
+
+impl SpendWitness {
+    /// Witness values with the signatures of `message`, usually the `jet::sig_all_hash`
+    /// of the spending transaction.
+    pub fn fill(&self, message: &Message) -> Result<WitnessValues> {
+        let mut json = self.json.clone();
+        if let Some(entries) = json.as_object_mut() {
+            for (name, keypair) in &self.signers {
+                let signature = secp256k1::SECP256K1.sign_schnorr(message, keypair);
+                entries.insert(
+                    name.clone(),
+                    serde_json::json!({
+                        "value": format!("0x{}", hex::encode(signature.as_ref())),
+                        "type": self.expected[name].to_string(),
+                    }),
+                );
+            }
+        }
+        check_json(
+            json,
+            &self.file_name,
+            &self.inline,
+            &self.expected,
+            "witness",
+        )
     }
 }
 
+/// Load a witness like [`load_checked_witness`], where `Signature` entries may have a `key`
+/// (keystore key name) or a `key_file` (hex secret key, relative to the witness file)
+/// instead of a `value`.
+pub fn load_spend_witness(
+    path: Option<&PathBuf>,
+    inline: &[(String, String)],
+    interface: &ProgramInterface,
+) -> Result<SpendWitness> {
+    let (mut json, file_name) = read_json(path, "witness")?;
+    let base_dir = path
+        .and_then(|path| path.parent())
+        .unwrap_or(Path::new("."));
+    let signature_ty = ResolvedType::parse_from_str("Signature")
+        .map_err(|e| anyhow::anyhow!("Failed to parse type `Signature`: {}", e))?;
+
+    let mut signers = Vec::new();
+    if let Some(entries) = json.as_object_mut() {
+        for (name, entry) in entries.iter() {
+            let field = |key: &str| entry.get(key).and_then(|v| v.as_str());
+            let keypair = match (field("key"), field("key_file")) {
+                (None, None) => continue,
+                (Some(key), None) => KeyRef::from_str(key)
+                    .map_err(|e| anyhow::anyhow!(e))
+                    .and_then(|key| key.keypair()),
+                (None, Some(key_file)) => load_key_file(&base_dir.join(key_file)),
+                (Some(_), Some(_)) => Err(anyhow::anyhow!("`key` and `key_file` are exclusive")),
+            }
+            .with_context(|| format!("Invalid witness file: {}\n  $.{}", file_name, name))?;
+            anyhow::ensure!(
+                interface.witnesses.get(name) == Some(&signature_ty),
+                "Invalid witness file: {}\n  $.{}: only `Signature` witnesses can be signed with a key",
+                file_name,
+                name
+            );
+            anyhow::ensure!(
+                entry.get("value").is_none(),
+                "Invalid witness file: {}\n  $.{}: a signed witness has no `value`",
+                file_name,
+                name
+            );
+            signers.push((name.clone(), keypair));
+        }
+        for (name, _) in &signers {
+            entries.remove(name);
+        }
+    }
+
+    let witness = SpendWitness {
+        json,
+        file_name,
+        inline: inline.to_vec(),
+        expected: interface.witnesses.clone(),
+        signers,
+    };
+    // Report invalid entries now, before the transaction is built
+    witness.fill(&Message::from_digest([0; 32]))?;
+    Ok(witness)
+}
+
+/// Load arguments from a JSON file and inline values,
+/// checking them against the parameters declared by the program.
+pub fn load_checked_arguments(
+    path: Option<&PathBuf>,
+    inline: &[(String, String)],
+    interface: &ProgramInterface,
+) -> Result<Arguments> {
+    load_checked(path, inline, &interface.parameters, "argument")
+}
+
+/// Check arguments in the argument file format (e.g. recorded in build artifacts)
+/// and inline values against the parameters declared by the program.
+pub fn check_arguments_json(
+    json: serde_json::Value,
+    source_name: &str,
+    inline: &[(String, String)],
+    interface: &ProgramInterface,
+) -> Result<serde_json::Value> {
+    check_json(json, source_name, inline, &interface.parameters, "argument")
+}
+
+/// Load arguments like [`load_checked_arguments`], keeping them in the argument file format.
+pub fn load_checked_arguments_json(
+    path: Option<&PathBuf>,
+    inline: &[(String, String)],
+    interface: &ProgramInterface,
+) -> Result<serde_json::Value> {
+    load_checked(path, inline, &interface.parameters, "argument")
+}
+
+/// Parse a `NAME=VALUE` command line assignment.
+pub fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VALUE, found `{}`", s))?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(format!("missing name in `{}`", s));
+    }
+    Ok((name.to_string(), value.trim().to_string()))
+}
+
 /// Get program name from source path.
 ///
 /// Program name is determined by the following rules:
 /// 1. If file name is not main.simf, use it as program name (strip extension)
 /// 2. If file is in <project dir>/src/<simf file>, use project name
 /// 3. If file is in */<package name>/<simf file>, use package name
-pub fn get_program_name(source_path: &PathBuf) -> Result<String> {
+pub fn get_program_name(source_path: &Path) -> Result<String> {
     let canonical_path = source_path
         .canonicalize()
-        .unwrap_or_else(|_| source_path.clone());
+        .unwrap_or_else(|_| source_path.to_path_buf());
     let components: Vec<_> = canonical_path.components().collect();
 
     // Rule 0: if file name is not main.simf, use it as program name (strip extension)
@@ -75,3 +287,16 @@ pub fn get_program_name(source_path: &PathBuf) -> Result<String> {
         source_path.display()
     ))
 }
+
+/// Find all `*.simf` files recursively in a directory.
+pub fn find_simf_files(dir: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("simf") {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
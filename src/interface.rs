@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use simfony::parse::ParseFromStr;
+use simfony::types::{ResolvedType, TypeInner};
+use simfony::value::Value;
+use simfony::{ast, parse};
+
+/// Names and types of the parameters and witnesses declared by a program
+#[derive(Debug, Clone, Default)]
+pub struct ProgramInterface {
+    pub parameters: BTreeMap<String, ResolvedType>,
+    pub witnesses: BTreeMap<String, ResolvedType>,
+}
+
+/// Analyze the program source and collect its parameters and witnesses.
+///
+/// Unlike compilation, this does not require the values of the parameters.
+pub fn program_interface(source: &str) -> Result<ProgramInterface> {
+    let parsed = parse::Program::parse_from_str(source).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let program = ast::Program::analyze(&parsed).map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    Ok(ProgramInterface {
+        parameters: program
+            .parameters()
+            .iter()
+            .map(|(name, ty)| (name.to_string(), ty.clone()))
+            .collect(),
+        witnesses: program
+            .witness_types()
+            .iter()
+            .map(|(name, ty)| (name.to_string(), ty.clone()))
+            .collect(),
+    })
+}
+
+/// SimplicityHL literal of the all-zero value of a type.
+pub fn default_literal(ty: &ResolvedType) -> Result<String> {
+    let literal = match ty.as_inner() {
+        TypeInner::Boolean => "false".to_string(),
+        TypeInner::UInt(uint) => {
+            let bits = uint.bit_width().get();
+            // Hex literals must match the width exactly and need whole nibbles
+            if bits >= 8 {
+                format!("0x{}", "0".repeat(bits / 4))
+            } else {
+                "0".to_string()
+            }
+        }
+        TypeInner::Either(left, _) => format!("Left({})", default_literal(left)?),
+        TypeInner::Option(_) => "None".to_string(),
+        TypeInner::Tuple(elements) => match elements.len() {
+            1 => format!("({},)", default_literal(&elements[0])?),
+            _ => format!(
+                "({})",
+                elements
+                    .iter()
+                    .map(|e| default_literal(e))
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            ),
+        },
+        TypeInner::Array(element, size) => {
+            format!("[{}]", vec![default_literal(element)?; *size].join(", "))
+        }
+        TypeInner::List(_, _) => "list![]".to_string(),
+        _ => anyhow::bail!("No default value for type `{}`", ty),
+    };
+    Ok(literal)
+}
+
+/// Template JSON in the format of witness and argument files, filled with zero values.
+pub fn template(entries: &BTreeMap<String, ResolvedType>) -> Result<serde_json::Value> {
+    let entries = entries
+        .iter()
+        .map(|(name, ty)| {
+            let value = default_literal(ty)
+                .with_context(|| format!("Failed to create a template for `{}`", name))?;
+            Ok((
+                name.clone(),
+                json!({
+                    "value": value,
+                    "type": ty.to_string(),
+                }),
+            ))
+        })
+        .collect::<Result<serde_json::Map<_, _>>>()?;
+    Ok(entries.into())
+}
+
+/// Check a witness or argument file against the declared names and types.
+///
+/// Returns one error per missing, extra or mistyped entry, prefixed with its JSON path.
+/// `kind` names the entries in messages (e.g. `witness`).
+pub fn check_entries(
+    json: &serde_json::Value,
+    expected: &BTreeMap<String, ResolvedType>,
+    kind: &str,
+) -> Vec<String> {
+    let Some(entries) = json.as_object() else {
+        return vec![format!(
+            "$: expected an object mapping {} names to values",
+            kind
+        )];
+    };
+    let mut errors = Vec::new();
+
+    for (name, ty) in expected {
+        if !entries.contains_key(name) {
+            errors.push(format!("$.{}: missing {} of type `{}`", name, kind, ty));
+        }
+    }
+
+    for (name, entry) in entries {
+        let Some(expected_ty) = expected.get(name) else {
+            errors.push(format!(
+                "$.{}: unexpected {}, the program does not declare it",
+                name, kind
+            ));
+            continue;
+        };
+
+        let field = |key: &str| entry.get(key).and_then(|v| v.as_str());
+        let (Some(value), Some(ty)) = (field("value"), field("type")) else {
+            errors.push(format!(
+                "$.{}: expected an object with string fields `value` and `type` (type: `{}`)",
+                name, expected_ty
+            ));
+            continue;
+        };
+
+        match ResolvedType::parse_from_str(ty) {
+            Ok(ty) if &ty == expected_ty => {}
+            Ok(_) => {
+                errors.push(format!(
+                    "$.{}.type: expected `{}`, found `{}`",
+                    name, expected_ty, ty
+                ));
+                continue;
+            }
+            Err(e) => {
+                errors.push(format!(
+                    "$.{}.type: invalid type `{}` (expected `{}`): {}",
+                    name, ty, expected_ty, e
+                ));
+                continue;
+            }
+        }
+
+        if let Err(e) = Value::parse_from_str(value, expected_ty) {
+            errors.push(format!(
+                "$.{}.value: invalid value of type `{}`: {}",
+                name, expected_ty, e
+            ));
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::from_json;
+    use simfony::{Arguments, WitnessValues};
+
+    const PROGRAM: &str = r#"
+fn main() {
+    let sig: Signature = witness::SIG;
+    let path: Either<(u1, u8), [u16; 2]> = witness::PATH;
+    let maybe: Option<(u256,)> = witness::MAYBE;
+    let items: List<u32, 4> = witness::ITEMS;
+    let flag: bool = witness::FLAG;
+    let pk: Pubkey = param::PK;
+    jet::bip_0340_verify((pk, jet::sig_all_hash()), sig);
+}
+"#;
+
+    #[test]
+    fn test_templates_load() {
+        let interface = program_interface(PROGRAM).unwrap();
+        assert_eq!(
+            interface.witnesses.keys().collect::<Vec<_>>(),
+            ["FLAG", "ITEMS", "MAYBE", "PATH", "SIG"]
+        );
+        assert_eq!(interface.parameters.keys().collect::<Vec<_>>(), ["PK"]);
+
+        let witness = template(&interface.witnesses).unwrap();
+        from_json::<WitnessValues>(&witness).unwrap();
+        let arguments = template(&interface.parameters).unwrap();
+        from_json::<Arguments>(&arguments).unwrap();
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let interface = program_interface(PROGRAM).unwrap();
+        let mut witness = template(&interface.witnesses).unwrap();
+        assert!(check_entries(&witness, &interface.witnesses, "witness").is_empty());
+
+        witness.as_object_mut().unwrap().remove("SIG");
+        witness["FLAG"]["type"] = "u8".into();
+        witness["PATH"]["value"] = "Right(1)".into();
+        witness["EXTRA"] = json!({"value": "0", "type": "u1"});
+        let errors = check_entries(&witness, &interface.witnesses, "witness");
+        assert_eq!(errors.len(), 4);
+        assert!(errors.contains(&"$.SIG: missing witness of type `[u8; 64]`".to_string()));
+        assert!(errors
+            .contains(&"$.EXTRA: unexpected witness, the program does not declare it".to_string()));
+        assert!(errors.contains(&"$.FLAG.type: expected `bool`, found `u8`".to_string()));
+        assert!(errors.iter().any(|e| e.starts_with("$.PATH.value")));
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use elements::secp256k1_zkp::{self as secp256k1, Keypair, Scalar, SecretKey, XOnlyPublicKey};
+use hex::FromHex;
+
+use crate::script::unspendable_key;
+
+/// Environment variable overriding the keystore directory
+pub const KEYSTORE_DIR_VAR: &str = "SIMPLY_KEYSTORE";
+
+/// Extension of key files in the keystore
+const KEY_FILE_EXTENSION: &str = "key";
+
+/// Extension of NUMS tweak files in the keystore
+const NUMS_FILE_EXTENSION: &str = "nums";
+
+/// Prefix of NUMS keys on the command line
+const NUMS_PREFIX: &str = "nums:";
+
+/// Keystore directory: `$SIMPLY_KEYSTORE`, or `~/.simply/keys` by default.
+pub fn keystore_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(KEYSTORE_DIR_VAR) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = std::env::var_os("HOME").ok_or_else(|| {
+        anyhow!(
+            "Cannot locate the keystore: HOME is not set, set {} instead",
+            KEYSTORE_DIR_VAR
+        )
+    })?;
+    Ok(Path::new(&home).join(".simply").join("keys"))
+}
+
+fn check_name(name: &str) -> Result<()> {
+    anyhow::ensure!(
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+        "Invalid key name `{}`, use letters, digits, `-` and `_`",
+        name
+    );
+    Ok(())
+}
+
+fn entry_path(dir: &Path, name: &str, extension: &str) -> Result<PathBuf> {
+    check_name(name)?;
+    Ok(dir.join(format!("{}.{}", name, extension)))
+}
+
+/// Parse a hex-encoded secret key (32 bytes).
+pub fn parse_secret_key(hex: &str) -> Result<SecretKey> {
+    let bytes = <[u8; 32]>::from_hex(hex.trim())
+        .with_context(|| "Failed to decode secret key hex (expected 32 bytes)")?;
+    SecretKey::from_slice(&bytes).with_context(|| "Invalid secret key")
+}
+
+/// Store a secret key under `name`, the file is only readable by the owner.
+pub fn store_key(dir: &Path, name: &str, secret_key: &SecretKey) -> Result<PathBuf> {
+    store_secret(dir, name, KEY_FILE_EXTENSION, &secret_key.secret_bytes())
+}
+
+/// Store a NUMS tweak under `name`, see [`nums_key`].
+pub fn store_nums(dir: &Path, name: &str, tweak: &Scalar) -> Result<PathBuf> {
+    store_secret(dir, name, NUMS_FILE_EXTENSION, &tweak.to_be_bytes())
+}
+
+fn store_secret(dir: &Path, name: &str, extension: &str, secret: &[u8; 32]) -> Result<PathBuf> {
+    let path = entry_path(dir, name, extension)?;
+    anyhow::ensure!(
+        !path.exists(),
+        "Key `{}` already exists: {}",
+        name,
+        path.display()
+    );
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create keystore directory: {}", dir.display()))?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Failed to create key file: {}", path.display()))?;
+    std::io::Write::write_all(&mut file, format!("{}\n", hex::encode(secret)).as_bytes())
+        .with_context(|| format!("Failed to write key file: {}", path.display()))?;
+    Ok(path)
+}
+
+fn load_secret(dir: &Path, name: &str, extension: &str) -> Result<(String, PathBuf)> {
+    let path = entry_path(dir, name, extension)?;
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Key `{}` not found in keystore: {}", name, dir.display()))?;
+    Ok((content, path))
+}
+
+/// Load the key pair stored under `name`.
+pub fn load_keypair(dir: &Path, name: &str) -> Result<Keypair> {
+    let (content, path) = load_secret(dir, name, KEY_FILE_EXTENSION)?;
+    let secret_key = parse_secret_key(&content)
+        .with_context(|| format!("Invalid key file: {}", path.display()))?;
+    Ok(Keypair::from_secret_key(secp256k1::SECP256K1, &secret_key))
+}
+
+/// Load a key pair from a file holding a hex-encoded secret key, outside the keystore.
+pub fn load_key_file(path: &Path) -> Result<Keypair> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file: {}", path.display()))?;
+    let secret_key = parse_secret_key(&content)
+        .with_context(|| format!("Invalid key file: {}", path.display()))?;
+    Ok(Keypair::from_secret_key(secp256k1::SECP256K1, &secret_key))
+}
+
+/// Load the NUMS tweak stored under `name`.
+pub fn load_nums(dir: &Path, name: &str) -> Result<Scalar> {
+    let (content, path) = load_secret(dir, name, NUMS_FILE_EXTENSION)?;
+    parse_tweak(&content).with_context(|| format!("Invalid NUMS file: {}", path.display()))
+}
+
+/// Parse a hex-encoded NUMS tweak (32-byte scalar).
+pub fn parse_tweak(hex: &str) -> Result<Scalar> {
+    let bytes = <[u8; 32]>::from_hex(hex.trim())
+        .with_context(|| "Failed to decode tweak hex (expected 32 bytes)")?;
+    Scalar::from_be_bytes(bytes).map_err(|_| anyhow!("Tweak is not a valid scalar"))
+}
+
+/// Provably unspendable key `H + rG`, where `H` is the NUMS point of [`unspendable_key`].
+///
+/// Unlike `H`, the key cannot be recognized by observers, while anyone who knows `r`
+/// can check that nobody knows its discrete logarithm.
+pub fn nums_key(tweak: &Scalar) -> Result<XOnlyPublicKey> {
+    let (key, _parity) = unspendable_key()
+        .add_tweak(secp256k1::SECP256K1, tweak)
+        .map_err(|e| anyhow!("Failed to tweak NUMS point: {}", e))?;
+    Ok(key)
+}
+
+/// Names of the stored keys, sorted.
+pub fn list_keys(dir: &Path) -> Result<Vec<String>> {
+    list_entries(dir, KEY_FILE_EXTENSION)
+}
+
+/// Names of the stored NUMS tweaks, sorted.
+pub fn list_nums(dir: &Path) -> Result<Vec<String>> {
+    list_entries(dir, NUMS_FILE_EXTENSION)
+}
+
+fn list_entries(dir: &Path, extension: &str) -> Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)
+        .with_context(|| format!("Failed to read keystore directory: {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) == Some(extension) {
+            if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Internal key given on the command line: an x-only public key in hex, a keystore key name,
+/// or `nums:<name>` for a stored NUMS tweak.
+#[derive(Debug, Clone)]
+pub enum KeyRef {
+    PublicKey(XOnlyPublicKey),
+    Keystore(String),
+    Nums(String),
+}
+
+impl std::str::FromStr for KeyRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()) {
+            let bytes = hex::decode(s).map_err(|e| e.to_string())?;
+            return XOnlyPublicKey::from_slice(&bytes)
+                .map(KeyRef::PublicKey)
+                .map_err(|e| format!("invalid x-only public key: {}", e));
+        }
+        if let Some(name) = s.strip_prefix(NUMS_PREFIX) {
+            check_name(name).map_err(|e| e.to_string())?;
+            return Ok(KeyRef::Nums(name.to_string()));
+        }
+        check_name(s).map_err(|e| e.to_string())?;
+        Ok(KeyRef::Keystore(s.to_string()))
+    }
+}
+
+impl KeyRef {
+    /// X-only public key, loading it from the keystore if needed.
+    pub fn public_key(&self) -> Result<XOnlyPublicKey> {
+        match self {
+            KeyRef::PublicKey(key) => Ok(*key),
+            KeyRef::Keystore(name) => {
+                let keypair = load_keypair(&keystore_dir()?, name)?;
+                Ok(keypair.x_only_public_key().0)
+            }
+            KeyRef::Nums(name) => nums_key(&load_nums(&keystore_dir()?, name)?),
+        }
+    }
+
+    /// Key pair for signing, only available for keystore keys.
+    pub fn keypair(&self) -> Result<Keypair> {
+        match self {
+            KeyRef::PublicKey(key) => Err(anyhow!(
+                "The secret key of {} is unknown, refer to the key by its keystore name",
+                key
+            )),
+            KeyRef::Keystore(name) => load_keypair(&keystore_dir()?, name),
+            KeyRef::Nums(name) => Err(anyhow!(
+                "NUMS key `{}` is unspendable, it cannot sign a key-path spend",
+                name
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret_key = parse_secret_key(&"01".repeat(32)).unwrap();
+
+        assert!(list_keys(dir.path()).unwrap().is_empty());
+        store_key(dir.path(), "alice", &secret_key).unwrap();
+        assert!(store_key(dir.path(), "alice", &secret_key).is_err());
+        assert!(store_key(dir.path(), "../bob", &secret_key).is_err());
+        assert_eq!(list_keys(dir.path()).unwrap(), ["alice"]);
+
+        let keypair = load_keypair(dir.path(), "alice").unwrap();
+        assert_eq!(keypair.secret_key(), secret_key);
+        assert!(load_keypair(dir.path(), "bob").is_err());
+
+        let key_file = dir.path().join("alice.hex");
+        fs::write(&key_file, "01".repeat(32)).unwrap();
+        assert_eq!(load_key_file(&key_file).unwrap().secret_key(), secret_key);
+    }
+
+    #[test]
+    fn test_key_ref() {
+        let key = "98dca27744caa04828985cead41574097d1d772eec3a0ddf19349b0f8804c227";
+        assert!(matches!(
+            key.parse::<KeyRef>(),
+            Ok(KeyRef::PublicKey(pk)) if pk.to_string() == key
+        ));
+        assert!(matches!(
+            "alice".parse::<KeyRef>(),
+            Ok(KeyRef::Keystore(name)) if name == "alice"
+        ));
+        assert!(matches!(
+            "nums:deposit-1".parse::<KeyRef>(),
+            Ok(KeyRef::Nums(name)) if name == "deposit-1"
+        ));
+        assert!("not a key".parse::<KeyRef>().is_err());
+    }
+
+    #[test]
+    fn test_nums_key() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(nums_key(&Scalar::ZERO).unwrap(), unspendable_key());
+
+        let tweak = parse_tweak(&"02".repeat(32)).unwrap();
+        store_nums(dir.path(), "deposit", &tweak).unwrap();
+        assert_eq!(list_nums(dir.path()).unwrap(), ["deposit"]);
+        assert!(list_keys(dir.path()).unwrap().is_empty());
+
+        let key = nums_key(&load_nums(dir.path(), "deposit").unwrap()).unwrap();
+        assert_eq!(key, nums_key(&tweak).unwrap());
+        assert_ne!(key, unspendable_key());
+        assert!(parse_tweak(&"ff".repeat(32)).is_err());
+    }
+}
@@ -0,0 +1,919 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+use std::str::FromStr;
+
+use simfony::parse::{self, CallName, ExpressionInner, SingleExpressionInner, Statement};
+use simfony::pattern::Pattern;
+use simfony::str::{FunctionName, Identifier};
+use simfony::types::TypeDeconstructible;
+use simfony::{Arguments, CompiledProgram};
+use simplicity::dag::{DagLike, InternalSharing};
+use simplicity::jet::{Elements, Jet};
+use simplicity::node::Inner;
+use simplicity::Cost;
+
+use crate::diagnostics::SourceFile;
+
+/// Jets that verify signatures
+const SIGNATURE_JETS: &[&str] = &["bip_0340_verify", "check_sig_verify"];
+
+/// Jets whose output commits to the transaction outputs
+const OUTPUT_COMMITMENT_JETS: &[&str] = &[
+    "sig_all_hash",
+    "tx_hash",
+    "outputs_hash",
+    "output_hash",
+    "output_amounts_hash",
+    "output_scripts_hash",
+    "output_nonces_hash",
+    "output_range_proofs_hash",
+    "output_surjection_proofs_hash",
+];
+
+/// Jets that read the lock time or sequence of the transaction
+const TIMELOCK_READ_JETS: &[&str] = &[
+    "lock_time",
+    "tx_lock_height",
+    "tx_lock_time",
+    "tx_lock_distance",
+    "tx_lock_duration",
+    "tx_is_final",
+    "current_sequence",
+    "input_sequence",
+];
+
+/// Jets that enforce a lock time or sequence
+const TIMELOCK_CHECK_JETS: &[&str] = &[
+    "check_lock_height",
+    "check_lock_time",
+    "check_lock_distance",
+    "check_lock_duration",
+];
+
+/// Lint settings
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    /// Jets with a higher cost (in milliweight units) are reported as expensive
+    pub max_jet_cost: u32,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            max_jet_cost: 45_000,
+        }
+    }
+}
+
+/// Lint finding
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Range<usize>>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Render the diagnostic in compiler style.
+    pub fn render(&self, source: &SourceFile) -> String {
+        source.render(
+            &format!("warning[{}]", self.code),
+            &self.message,
+            self.span.clone(),
+            &self.notes,
+        )
+    }
+}
+
+/// Run all lints on a compiled program and its source.
+///
+/// The program must be compiled with debug symbols, which tell which calls of the source
+/// end up in the program. Calls in functions that `main` never reaches are not linted.
+pub fn lint(
+    source: &SourceFile,
+    compiled: &CompiledProgram,
+    arguments: &Arguments,
+    config: &LintConfig,
+) -> Vec<Diagnostic> {
+    let Ok(program) = source.parse() else {
+        return vec![];
+    };
+    let reachable = reachable_calls(source, compiled, arguments);
+    let jets = jet_call_sites(compiled, &reachable);
+    let summary = main_summary(source, &program);
+
+    let mut diagnostics = Vec::new();
+    if let Some(summary) = &summary {
+        diagnostics.extend(unconstrained_witnesses(summary));
+        diagnostics.extend(uncommitted_signatures(summary));
+    }
+    diagnostics.extend(failing_branches(source, &program, &reachable));
+    diagnostics.extend(missing_timelock_checks(&jets));
+    diagnostics.extend(expensive_jets(&jets, config));
+    diagnostics.sort_by_key(|d| d.span.as_ref().map(|s| s.start).unwrap_or(usize::MAX));
+    diagnostics
+}
+
+/// Calls of the source that are part of the compiled program, keyed by their location.
+fn reachable_calls(
+    source: &SourceFile,
+    compiled: &CompiledProgram,
+    arguments: &Arguments,
+) -> BTreeMap<usize, (Range<usize>, CallName)> {
+    source
+        .tracked_calls(arguments, compiled.debug_symbols())
+        .into_values()
+        .map(|call| (call.span.start, (call.span, call.name)))
+        .collect()
+}
+
+/// Jets of the compiled program, keyed by their name, with their call sites in the source.
+///
+/// Jets that the compiler inserts, such as `verify` for assertions, have no call site.
+fn jet_call_sites(
+    compiled: &CompiledProgram,
+    reachable: &BTreeMap<usize, (Range<usize>, CallName)>,
+) -> BTreeMap<String, Vec<Range<usize>>> {
+    let mut jets: BTreeMap<String, Vec<Range<usize>>> = compiled
+        .commit()
+        .post_order_iter::<InternalSharing>()
+        .filter_map(|data| match data.node.inner() {
+            Inner::Jet(jet) => Some((jet.to_string(), vec![])),
+            _ => None,
+        })
+        .collect();
+    for (span, name) in reachable.values() {
+        if let CallName::Jet(jet) = name {
+            if let Some(sites) = jets.get_mut(jet.as_inner()) {
+                sites.push(span.clone());
+            }
+        }
+    }
+    jets
+}
+
+/// Value that a SimplicityHL expression can depend on.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Origin {
+    /// Witness with the given name
+    Witness(String),
+    /// Output of the jet with the given name
+    Jet(String),
+    /// Parameter of the current function at the given position
+    Param(usize),
+}
+
+type Origins = BTreeSet<Origin>;
+
+/// Call that constrains the values that it depends on.
+#[derive(Debug, Clone)]
+struct Check {
+    /// Location of the signature verification, `None` for other checks
+    signature: Option<Range<usize>>,
+    origins: Origins,
+}
+
+/// Dataflow of a function: what its checks and its result depend on.
+#[derive(Debug, Clone, Default)]
+struct Summary {
+    result: Origins,
+    checks: Vec<Check>,
+    /// Witnesses that the function reads, with the location of the first read
+    witnesses: BTreeMap<String, Range<usize>>,
+}
+
+impl Summary {
+    /// Summary of a call of the function with arguments that depend on `args`.
+    fn instantiate(&self, args: &[Origins]) -> Summary {
+        let substitute = |origins: &Origins| -> Origins {
+            origins
+                .iter()
+                .flat_map(|origin| match origin {
+                    Origin::Param(index) => args.get(*index).cloned().unwrap_or_default(),
+                    _ => Origins::from([origin.clone()]),
+                })
+                .collect()
+        };
+        Summary {
+            result: substitute(&self.result),
+            checks: self
+                .checks
+                .iter()
+                .map(|check| Check {
+                    signature: check.signature.clone(),
+                    origins: substitute(&check.origins),
+                })
+                .collect(),
+            witnesses: self.witnesses.clone(),
+        }
+    }
+}
+
+/// Summarize the functions in order of definition and return the summary of `main`.
+fn main_summary(source: &SourceFile, program: &parse::Program) -> Option<Summary> {
+    let mut summaries = HashMap::<FunctionName, Summary>::new();
+    for item in program.items() {
+        let parse::Item::Function(function) = item else {
+            continue;
+        };
+        let mut dataflow = Dataflow {
+            source,
+            summaries: &summaries,
+            summary: Summary::default(),
+        };
+        let mut variables: HashMap<Identifier, Origins> = function
+            .params()
+            .iter()
+            .enumerate()
+            .map(|(index, param)| {
+                (
+                    param.identifier().clone(),
+                    Origins::from([Origin::Param(index)]),
+                )
+            })
+            .collect();
+        let result = dataflow.expression(function.body(), &mut variables);
+        let mut summary = dataflow.summary;
+        summary.result = result;
+        summaries.insert(function.name().clone(), summary);
+    }
+    summaries.remove(&FunctionName::main())
+}
+
+/// Dataflow analysis of a function body.
+struct Dataflow<'a> {
+    source: &'a SourceFile,
+    /// Summaries of the functions defined so far
+    summaries: &'a HashMap<FunctionName, Summary>,
+    summary: Summary,
+}
+
+impl Dataflow<'_> {
+    /// Analyze an expression and return what its value depends on.
+    fn expression(
+        &mut self,
+        expression: &parse::Expression,
+        variables: &mut HashMap<Identifier, Origins>,
+    ) -> Origins {
+        let single = match expression.inner() {
+            ExpressionInner::Single(single) => single,
+            ExpressionInner::Block(statements, last) => {
+                let mut variables = variables.clone();
+                for statement in statements.iter() {
+                    match statement {
+                        Statement::Assignment(assignment) => {
+                            let origins = self.expression(assignment.expression(), &mut variables);
+                            bind(assignment.pattern(), &origins, &mut variables);
+                        }
+                        Statement::Expression(expression) => {
+                            self.expression(expression, &mut variables);
+                        }
+                    }
+                }
+                return last
+                    .as_ref()
+                    .map(|last| self.expression(last, &mut variables))
+                    .unwrap_or_default();
+            }
+        };
+
+        match single.inner() {
+            SingleExpressionInner::Witness(name) => {
+                let name = name.as_inner().to_string();
+                self.summary
+                    .witnesses
+                    .entry(name.clone())
+                    .or_insert_with(|| self.source.range(single.as_ref()));
+                Origins::from([Origin::Witness(name)])
+            }
+            SingleExpressionInner::Variable(identifier) => {
+                variables.get(identifier).cloned().unwrap_or_default()
+            }
+            SingleExpressionInner::Call(call) => self.call(call, variables),
+            SingleExpressionInner::Match(match_) => {
+                let mut origins = self.expression(match_.scrutinee(), variables);
+                for arm in [match_.left(), match_.right()] {
+                    let mut variables = variables.clone();
+                    if let Some(identifier) = arm.pattern().as_variable() {
+                        variables.insert(identifier.clone(), origins.clone());
+                    }
+                    let arm_origins = self.expression(arm.expression(), &mut variables);
+                    origins.extend(arm_origins);
+                }
+                origins
+            }
+            SingleExpressionInner::Either(either) => {
+                self.expression(either.as_ref().either(|e| e, |e| e), variables)
+            }
+            SingleExpressionInner::Option(Some(inner))
+            | SingleExpressionInner::Expression(inner) => self.expression(inner, variables),
+            SingleExpressionInner::Tuple(elements)
+            | SingleExpressionInner::Array(elements)
+            | SingleExpressionInner::List(elements) => elements
+                .iter()
+                .flat_map(|element| self.expression(element, variables))
+                .collect(),
+            _ => Origins::new(),
+        }
+    }
+
+    /// Analyze a call and return what its output depends on.
+    fn call(
+        &mut self,
+        call: &parse::Call,
+        variables: &mut HashMap<Identifier, Origins>,
+    ) -> Origins {
+        let is_signature_check =
+            matches!(call.name(), CallName::Jet(jet) if SIGNATURE_JETS.contains(&jet.as_inner()));
+        // Signature jets take `((pk, msg), sig)`, only the message decides the commitment
+        let mut message = None;
+        let args: Vec<Origins> = call
+            .args()
+            .iter()
+            .enumerate()
+            .map(|(index, arg)| match pair(arg) {
+                Some((pk, msg)) if is_signature_check && index == 0 => {
+                    let mut origins = self.expression(pk, variables);
+                    let msg = self.expression(msg, variables);
+                    origins.extend(msg.iter().cloned());
+                    message = Some(msg);
+                    origins
+                }
+                _ => {
+                    let origins = self.expression(arg, variables);
+                    if is_signature_check && index == 0 {
+                        message = Some(origins.clone());
+                    }
+                    origins
+                }
+            })
+            .collect();
+        let all_args: Origins = args.iter().flatten().cloned().collect();
+
+        match call.name() {
+            CallName::Jet(jet) => {
+                let name = jet.as_inner();
+                if is_signature_check {
+                    self.summary.checks.push(Check {
+                        signature: Some(self.source.range(call.as_ref())),
+                        origins: message.unwrap_or_default(),
+                    });
+                }
+                if is_unit_jet(name) {
+                    self.check(all_args.clone());
+                }
+                let mut origins = all_args;
+                origins.insert(Origin::Jet(name.to_string()));
+                origins
+            }
+            CallName::Assert
+            | CallName::Unwrap
+            | CallName::UnwrapLeft(_)
+            | CallName::UnwrapRight(_) => {
+                self.check(all_args.clone());
+                all_args
+            }
+            CallName::Custom(function)
+            | CallName::Fold(function, _)
+            | CallName::ForWhile(function) => match self.summaries.get(function) {
+                Some(callee) => {
+                    let callee = callee.instantiate(&args);
+                    self.summary.checks.extend(callee.checks);
+                    for (name, span) in callee.witnesses {
+                        self.summary.witnesses.entry(name).or_insert(span);
+                    }
+                    callee.result
+                }
+                None => all_args,
+            },
+            _ => all_args,
+        }
+    }
+
+    fn check(&mut self, origins: Origins) {
+        self.summary.checks.push(Check {
+            signature: None,
+            origins,
+        });
+    }
+}
+
+/// Bind the variables of a pattern to a value that depends on `origins`.
+fn bind(pattern: &Pattern, origins: &Origins, variables: &mut HashMap<Identifier, Origins>) {
+    match pattern {
+        Pattern::Identifier(identifier) => {
+            variables.insert(identifier.clone(), origins.clone());
+        }
+        Pattern::Ignore => {}
+        Pattern::Tuple(elements) | Pattern::Array(elements) => {
+            for element in elements.iter() {
+                bind(element, origins, variables);
+            }
+        }
+    }
+}
+
+/// Check if a jet has no output, so its only effect is to fail or not.
+fn is_unit_jet(name: &str) -> bool {
+    Elements::from_str(name).is_ok_and(|jet| {
+        simfony::jet::target_type(jet)
+            .resolve_builtin()
+            .is_ok_and(|ty| ty.is_unit())
+    })
+}
+
+/// Witnesses that no check of the program depends on.
+fn unconstrained_witnesses(summary: &Summary) -> Vec<Diagnostic> {
+    summary
+        .witnesses
+        .iter()
+        .filter(|(name, _)| {
+            let origin = Origin::Witness(name.to_string());
+            !summary
+                .checks
+                .iter()
+                .any(|check| check.origins.contains(&origin))
+        })
+        .map(|(name, span)| Diagnostic {
+            code: "unconstrained-witness",
+            message: format!("witness `{}` is not constrained by any check", name),
+            span: Some(span.clone()),
+            notes: vec![
+                "an unconstrained witness can be replaced by anyone who relays the transaction"
+                    .to_string(),
+            ],
+        })
+        .collect()
+}
+
+/// Signature checks whose message does not commit to the transaction outputs.
+fn uncommitted_signatures(summary: &Summary) -> Vec<Diagnostic> {
+    let mut reported = HashSet::new();
+    summary
+        .checks
+        .iter()
+        .filter_map(|check| {
+            let span = check.signature.as_ref()?;
+            let committed = check.origins.iter().any(
+                |origin| matches!(origin, Origin::Jet(jet) if OUTPUT_COMMITMENT_JETS.contains(&jet.as_str())),
+            );
+            (!committed && reported.insert(span.start)).then(|| Diagnostic {
+                code: "uncommitted-signature",
+                message: "signed message does not commit to the transaction outputs".to_string(),
+                span: Some(span.clone()),
+                notes: vec![
+                    "sign a message derived from `jet::sig_all_hash()` or the output hashes, otherwise the outputs can be changed after signing"
+                        .to_string(),
+                ],
+            })
+        })
+        .collect()
+}
+
+/// Elements of an expression that is a tuple of two.
+fn pair(expression: &parse::Expression) -> Option<(&parse::Expression, &parse::Expression)> {
+    match expression.inner() {
+        ExpressionInner::Single(single) => match single.inner() {
+            SingleExpressionInner::Tuple(elements) if elements.len() == 2 => {
+                Some((&elements[0], &elements[1]))
+            }
+            SingleExpressionInner::Expression(inner) => pair(inner),
+            _ => None,
+        },
+        ExpressionInner::Block(..) => None,
+    }
+}
+
+/// Call that always fails, if the expression ends in one.
+fn failing_call(expression: &parse::Expression) -> Option<&parse::Call> {
+    match expression.inner() {
+        ExpressionInner::Block(statements, last) => statements
+            .iter()
+            .find_map(|statement| match statement {
+                Statement::Assignment(assignment) => failing_call(assignment.expression()),
+                Statement::Expression(expression) => failing_call(expression),
+            })
+            .or_else(|| last.as_ref().and_then(|last| failing_call(last))),
+        ExpressionInner::Single(single) => match single.inner() {
+            SingleExpressionInner::Expression(inner) => failing_call(inner),
+            SingleExpressionInner::Call(call) => match call.name() {
+                CallName::Panic => Some(call),
+                CallName::Assert => match call.args().first().map(|arg| arg.inner()) {
+                    Some(ExpressionInner::Single(arg))
+                        if matches!(arg.inner(), SingleExpressionInner::Boolean(false)) =>
+                    {
+                        Some(call)
+                    }
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        },
+    }
+}
+
+/// Functions and match arms that always fail and code that follows an unconditional failure.
+fn failing_branches(
+    source: &SourceFile,
+    program: &parse::Program,
+    reachable: &BTreeMap<usize, (Range<usize>, CallName)>,
+) -> Vec<Diagnostic> {
+    let fails = |expression: &parse::Expression| {
+        failing_call(expression).is_some_and(|call| {
+            let span = source.range(call.as_ref());
+            reachable.get(&span.start).is_some_and(|(s, _)| *s == span)
+        })
+    };
+    let mut diagnostics = Vec::new();
+
+    for item in program.items() {
+        let parse::Item::Function(function) = item else {
+            continue;
+        };
+        if fails(function.body()) {
+            diagnostics.push(Diagnostic {
+                code: "always-fails",
+                message: format!("function `{}` always fails", function.name()),
+                span: Some(source.range(function.body().as_ref())),
+                notes: vec![],
+            });
+        }
+
+        let mut expressions = vec![function.body()];
+        while let Some(expression) = expressions.pop() {
+            let single = match expression.inner() {
+                ExpressionInner::Block(statements, last) => {
+                    let items: Vec<_> = statements
+                        .iter()
+                        .map(|statement| match statement {
+                            Statement::Assignment(assignment) => {
+                                (source.range(assignment.as_ref()), assignment.expression())
+                            }
+                            Statement::Expression(expression) => {
+                                (source.range(expression.as_ref()), expression)
+                            }
+                        })
+                        .chain(
+                            last.as_deref()
+                                .map(|last| (source.range(last.as_ref()), last)),
+                        )
+                        .collect();
+                    if let Some(index) = items.iter().position(|(_, e)| fails(e)) {
+                        if let (Some((next, _)), Some((last, _))) =
+                            (items.get(index + 1), items.last())
+                        {
+                            diagnostics.push(Diagnostic {
+                                code: "unreachable-code",
+                                message: "unreachable code after an unconditional failure"
+                                    .to_string(),
+                                span: Some(next.start..last.end),
+                                notes: vec![],
+                            });
+                        }
+                    }
+                    expressions.extend(items.into_iter().map(|(_, expression)| expression));
+                    continue;
+                }
+                ExpressionInner::Single(single) => single,
+            };
+            match single.inner() {
+                SingleExpressionInner::Match(match_) => {
+                    expressions.push(match_.scrutinee());
+                    for arm in [match_.left(), match_.right()] {
+                        if fails(arm.expression()) {
+                            diagnostics.push(Diagnostic {
+                                code: "always-fails",
+                                message: format!("branch `{}` always fails", arm.pattern()),
+                                span: Some(source.range(arm.expression().as_ref())),
+                                notes: vec![
+                                    "if this branch must never be taken, make sure the program cannot reach it with any witness"
+                                        .to_string(),
+                                ],
+                            });
+                        }
+                        expressions.push(arm.expression());
+                    }
+                }
+                SingleExpressionInner::Call(call) => expressions.extend(call.args().iter()),
+                SingleExpressionInner::Either(either) => {
+                    expressions.push(either.as_ref().either(|e| e, |e| e))
+                }
+                SingleExpressionInner::Option(Some(inner))
+                | SingleExpressionInner::Expression(inner) => expressions.push(inner),
+                SingleExpressionInner::Tuple(elements)
+                | SingleExpressionInner::Array(elements)
+                | SingleExpressionInner::List(elements) => expressions.extend(elements.iter()),
+                _ => {}
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Lock time or sequence values that are read but never enforced.
+fn missing_timelock_checks(jets: &BTreeMap<String, Vec<Range<usize>>>) -> Vec<Diagnostic> {
+    if TIMELOCK_CHECK_JETS.iter().any(|j| jets.contains_key(*j)) {
+        return vec![];
+    }
+    TIMELOCK_READ_JETS
+        .iter()
+        .filter_map(|j| jets.get(*j).map(|sites| (j, sites)))
+        .map(|(j, sites)| Diagnostic {
+            code: "missing-timelock-check",
+            message: format!(
+                "`jet::{}` is read but no lock time or sequence is enforced",
+                j
+            ),
+            span: sites.first().cloned(),
+            notes: vec![format!(
+                "use one of {} to enforce the timelock",
+                TIMELOCK_CHECK_JETS
+                    .iter()
+                    .map(|j| format!("`jet::{}`", j))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )],
+        })
+        .collect()
+}
+
+/// Jets that cost more than the configured threshold.
+fn expensive_jets(
+    jets: &BTreeMap<String, Vec<Range<usize>>>,
+    config: &LintConfig,
+) -> Vec<Diagnostic> {
+    let threshold = Cost::from_milliweight(config.max_jet_cost);
+    jets.iter()
+        .filter_map(|(name, sites)| {
+            let jet = Elements::from_str(name).ok()?;
+            (jet.cost() > threshold).then(|| Diagnostic {
+                code: "expensive-jet",
+                message: format!("`jet::{}` is expensive (cost: {})", name, jet.cost()),
+                span: sites.first().cloned(),
+                notes: vec![match sites.len() {
+                    0 => format!("no call site in the source, threshold: {}", threshold),
+                    n => format!("{} call site(s), threshold: {}", n, threshold),
+                }],
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_with(text: &str, config: &LintConfig) -> Vec<Diagnostic> {
+        let compiled = CompiledProgram::new(text, Arguments::default(), true).unwrap();
+        let source = SourceFile::new("test.simf".to_string(), text.to_string());
+        lint(&source, &compiled, &Arguments::default(), config)
+    }
+
+    /// Codes and source text of the diagnostics of a program.
+    fn findings(text: &str) -> Vec<(&'static str, &str)> {
+        lint_with(text, &LintConfig::default())
+            .into_iter()
+            .map(|d| (d.code, d.span.map_or("", |span| &text[span])))
+            .collect()
+    }
+
+    #[test]
+    fn test_unconstrained_witness() {
+        let text = r#"fn main() {
+    // let unused: u32 = witness::IN_COMMENT;
+    let x: u32 = witness::X;
+    let (carry, y): (bool, u32) = jet::add_32(x, 1);
+    let z: u32 = witness::Z;
+    assert!(jet::eq_32(z, 5));
+}
+"#;
+        assert_eq!(findings(text), [("unconstrained-witness", "witness::X")]);
+    }
+
+    #[test]
+    fn test_witness_constrained_by_function() {
+        let text = r#"fn check(value: u32) {
+    assert!(jet::le_32(value, 10));
+}
+
+fn main() {
+    let x: u32 = witness::X;
+    let (carry, y): (bool, u32) = jet::add_32(x, 1);
+    check(y);
+    match witness::PATH {
+        Left(a: u8) => assert!(jet::eq_8(a, 1)),
+        Right(b: u16) => assert!(jet::eq_16(b, 2)),
+    };
+}
+"#;
+        assert!(findings(text).is_empty());
+    }
+
+    #[test]
+    fn test_always_fails() {
+        let text = r#"fn main() {
+    let path: Either<u32, u16> = witness::PATH;
+    match path {
+        Left(x: u32) => assert!(jet::eq_32(x, 1)),
+        Right(y: u16) => {
+            assert!(false);
+        }
+    }
+}
+"#;
+        let diagnostics = lint_with(text, &LintConfig::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "always-fails");
+        assert_eq!(
+            diagnostics[0].message,
+            "branch `Right(y: u16)` always fails"
+        );
+    }
+
+    #[test]
+    fn test_failure_in_unused_function() {
+        let text = r#"fn unused() {
+    panic!();
+}
+
+fn main() {
+    // panic!();
+    let path: Either<u32, u32> = witness::PATH;
+    match path {
+        Left(x: u32) => assert!(jet::eq_32(x, 1)),
+        Right(y: u32) => assert!(jet::eq_32(y, 2)),
+    }
+}
+"#;
+        assert!(findings(text).is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let text = r#"fn main() {
+    let x: u32 = witness::X;
+    assert!(jet::eq_32(x, 1));
+    panic!();
+    let y: u32 = 2;
+    assert!(jet::eq_32(x, y))
+}
+"#;
+        let codes: Vec<_> = findings(text);
+        assert_eq!(
+            codes,
+            [
+                ("always-fails", &text[10..text.len() - 1]),
+                (
+                    "unreachable-code",
+                    "let y: u32 = 2;\n    assert!(jet::eq_32(x, y))"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assert_true_is_reachable() {
+        let text = r#"fn main() {
+    assert!(true);
+    let x: u32 = witness::X;
+    assert!(jet::eq_32(x, 1));
+}
+"#;
+        assert!(findings(text).is_empty());
+    }
+
+    const SIGNATURE_CHECK: &str = r#"fn checksig(msg: u256, sig: Signature) {
+    let pk: Pubkey = 0x79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798;
+    jet::bip_0340_verify((pk, msg), sig);
+}
+"#;
+
+    #[test]
+    fn test_uncommitted_signature() {
+        // Reading the output commitment somewhere else does not commit the signed message
+        let text = format!(
+            r#"{}
+fn main() {{
+    let hash: u256 = jet::sig_all_hash();
+    let msg: u256 = witness::MSG;
+    checksig(msg, witness::SIG);
+}}
+"#,
+            SIGNATURE_CHECK
+        );
+        let findings: Vec<_> = findings(&text)
+            .into_iter()
+            .filter(|(code, _)| *code != "expensive-jet")
+            .collect();
+        assert_eq!(
+            findings,
+            [(
+                "uncommitted-signature",
+                "jet::bip_0340_verify((pk, msg), sig)"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_signature_commitment_from_key() {
+        // Only the message commits to the outputs, not the public key or the signature
+        let text = r#"fn main() {
+    let msg: u256 = witness::MSG;
+    let sig: Signature = witness::SIG;
+    jet::bip_0340_verify((jet::sig_all_hash(), msg), sig);
+}
+"#;
+        assert_eq!(
+            findings(text)
+                .into_iter()
+                .filter(|(code, _)| *code != "expensive-jet")
+                .collect::<Vec<_>>(),
+            [(
+                "uncommitted-signature",
+                "jet::bip_0340_verify((jet::sig_all_hash(), msg), sig)"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_committed_signature() {
+        let text = format!(
+            r#"{}
+fn main() {{
+    let ctx: Ctx8 = jet::sha_256_ctx_8_init();
+    let ctx: Ctx8 = jet::sha_256_ctx_8_add_32(ctx, jet::sig_all_hash());
+    checksig(jet::sha_256_ctx_8_finalize(ctx), witness::SIG);
+}}
+"#,
+            SIGNATURE_CHECK
+        );
+        let codes: Vec<_> = findings(&text).into_iter().map(|(code, _)| code).collect();
+        assert_eq!(codes, ["expensive-jet"]);
+    }
+
+    #[test]
+    fn test_missing_timelock_check() {
+        let text = r#"fn main() {
+    let height: Height = jet::tx_lock_height();
+    assert!(jet::le_32(1000, height));
+}
+"#;
+        assert_eq!(
+            findings(text),
+            [("missing-timelock-check", "jet::tx_lock_height()")]
+        );
+    }
+
+    #[test]
+    fn test_enforced_timelock() {
+        let text = r#"fn main() {
+    let height: Height = jet::tx_lock_height();
+    assert!(jet::le_32(1000, height));
+    jet::check_lock_height(1000);
+}
+"#;
+        assert!(findings(text).is_empty());
+    }
+
+    #[test]
+    fn test_expensive_jet() {
+        let text = r#"fn unused() {
+    let (carry, sum): (bool, u64) = jet::add_64(1, 2);
+}
+
+fn main() {
+    let x: u32 = witness::X;
+    assert!(jet::eq_32(x, jet::max_32(1, 2)));
+    assert!(jet::eq_32(x, jet::max_32(2, 3)));
+}
+"#;
+        let config = LintConfig { max_jet_cost: 0 };
+        let diagnostics = lint_with(text, &config);
+        let mut names: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.code, d.span.clone().map_or("", |span| &text[span])))
+            .collect();
+        names.sort();
+        // `verify` is inserted by the compiler for the assertions
+        assert_eq!(
+            names,
+            [
+                ("expensive-jet", ""),
+                ("expensive-jet", "jet::eq_32(x, jet::max_32(1, 2))"),
+                ("expensive-jet", "jet::max_32(1, 2)"),
+            ]
+        );
+        let verify = diagnostics.iter().find(|d| d.span.is_none()).unwrap();
+        assert!(verify.message.starts_with("`jet::verify`"));
+        assert_eq!(verify.notes, ["no call site in the source, threshold: 0"]);
+        let max = diagnostics
+            .iter()
+            .find(|d| d.message.starts_with("`jet::max_32`"))
+            .unwrap();
+        assert_eq!(max.notes, ["2 call site(s), threshold: 0"]);
+
+        assert!(lint_with(text, &LintConfig::default()).is_empty());
+    }
+}
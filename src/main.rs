@@ -1,25 +1,51 @@
 use clap::Parser;
 
 mod commands;
+mod config;
+mod diagnostics;
 mod esplora;
+mod formatter;
 mod helpers;
+mod interface;
+mod keystore;
+mod lint;
+mod memory;
 mod rpc;
 mod script;
+mod taptree;
 mod tracker;
 mod transaction;
 
-use commands::{build, deposit, run, sign, test, withdraw, Cli, Commands};
+use commands::{
+    arguments, asm, batch, build, deposit, disasm, fmt, init, inspect_tx, key, lint, new, nums,
+    pset, run, sighash, sign, test, verify, withdraw, witness, Cli, Commands,
+};
 
 fn main() {
     let cli = Cli::parse();
 
     let result = match cli.command {
+        Commands::New(args) => new(args),
+        Commands::Init(args) => init(args),
         Commands::Build(args) => build(args),
         Commands::Run(args) => run(args),
         Commands::Test(args) => test(args),
         Commands::Deposit(args) => deposit(args),
         Commands::Withdraw(args) => withdraw(args),
+        Commands::Batch(args) => batch(args),
+        Commands::Sighash(args) => sighash(args),
         Commands::Sign(args) => sign(args),
+        Commands::Fmt(args) => fmt(args),
+        Commands::Lint(args) => lint(args),
+        Commands::Asm(args) => asm(args),
+        Commands::Disasm(args) => disasm(args),
+        Commands::InspectTx(args) => inspect_tx(args),
+        Commands::Verify(args) => verify(args),
+        Commands::Key { command } => key(command),
+        Commands::Nums { command } => nums(command),
+        Commands::Pset { command } => pset(command),
+        Commands::Witness { command } => witness(command),
+        Commands::Arguments { command } => arguments(command),
     };
 
     if let Err(err) = result {
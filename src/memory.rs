@@ -0,0 +1,327 @@
+use std::fmt;
+use std::sync::Arc;
+
+use simplicity::jet::Elements;
+use simplicity::node::Inner;
+use simplicity::{Ihr, NodeBounds, RedeemNode};
+
+/// Event of a Bit Machine execution, as reported to the execution tracker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecEvent {
+    /// Branch taken by a `case`, `assertl` or `assertr` node
+    Branch { ihr: Ihr, right: bool },
+    /// Jet call, which stops the execution if it fails
+    Jet { success: bool },
+}
+
+/// Frame operation performed by the Bit Machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOp {
+    Push,
+    Pop,
+}
+
+/// Entry of the memory timeline.
+#[derive(Debug, Clone)]
+pub struct FrameEvent {
+    pub op: FrameOp,
+    /// Width of the frame in cells
+    pub width: usize,
+    /// Number of active frames after the operation
+    pub frames: usize,
+    /// Number of active cells after the operation
+    pub cells: usize,
+}
+
+/// Actual memory usage of a program execution.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryUsage {
+    pub peak_cells: usize,
+    pub peak_frames: usize,
+    pub timeline: Vec<FrameEvent>,
+    /// Whether the execution stopped at a failure before the end of the program
+    pub failed: bool,
+}
+
+impl MemoryUsage {
+    fn record(&mut self, op: FrameOp, width: usize) {
+        let (frames, cells) = match self.timeline.last() {
+            Some(last) => (last.frames, last.cells),
+            None => (0, 0),
+        };
+        let (frames, cells) = match op {
+            FrameOp::Push => (frames + 1, cells + width),
+            FrameOp::Pop => (frames - 1, cells - width),
+        };
+        self.peak_frames = self.peak_frames.max(frames);
+        self.peak_cells = self.peak_cells.max(cells);
+        self.timeline.push(FrameEvent {
+            op,
+            width,
+            frames,
+            cells,
+        });
+    }
+
+    /// Format actual usage against the static bounds of the program.
+    pub fn report(&self, bounds: &NodeBounds) -> String {
+        format!(
+            "Memory usage{}:\n  Peak cells: {} (static bound: {}, {})\n  Peak frames: {} (static bound: {}, {})",
+            if self.failed { " until the failure" } else { "" },
+            self.peak_cells,
+            bounds.extra_cells,
+            utilization(self.peak_cells, bounds.extra_cells),
+            self.peak_frames,
+            bounds.extra_frames,
+            utilization(self.peak_frames, bounds.extra_frames),
+        )
+    }
+
+    /// Format the timeline of frame pushes and pops, printing at most `limit` events.
+    pub fn format_timeline(&self, limit: usize) -> String {
+        let mut lines = vec!["Frame timeline:".to_string()];
+        for (step, event) in self.timeline.iter().take(limit).enumerate() {
+            let depth = match event.op {
+                FrameOp::Push => event.frames - 1,
+                FrameOp::Pop => event.frames,
+            };
+            lines.push(format!(
+                "  {:>6} {}{} {} cells (frames: {}, cells: {})",
+                step,
+                "  ".repeat(depth),
+                event.op,
+                event.width,
+                event.frames,
+                event.cells
+            ));
+        }
+        if self.timeline.len() > limit {
+            lines.push(format!("  ... {} more events", self.timeline.len() - limit));
+        }
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for FrameOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameOp::Push => f.write_str("push"),
+            FrameOp::Pop => f.write_str("pop "),
+        }
+    }
+}
+
+fn utilization(actual: usize, bound: usize) -> String {
+    if bound == 0 {
+        "n/a".to_string()
+    } else {
+        format!("{:.1}% used", actual as f64 * 100.0 / bound as f64)
+    }
+}
+
+enum Task<'a> {
+    Exec(&'a Arc<RedeemNode<Elements>>),
+    Pop(usize),
+}
+
+/// Replay the frame operations of an execution.
+///
+/// The Bit Machine only allocates frames in `comp` and `disconnect` nodes, so the memory
+/// usage is fully determined by the program and the branches taken by `case`, `assertl` and
+/// `assertr` nodes. The trace also contains the jet calls, to find where a failed execution
+/// stopped: at a failed jet, a `fail` node or a pruned branch (which the tracker does not see).
+pub fn replay(
+    program: &Arc<RedeemNode<Elements>>,
+    trace: &[ExecEvent],
+) -> anyhow::Result<MemoryUsage> {
+    let mut usage = MemoryUsage::default();
+    let mut trace = trace.iter();
+    let mut stack = vec![Task::Exec(program)];
+
+    while let Some(task) = stack.pop() {
+        let node = match task {
+            Task::Exec(node) => node,
+            Task::Pop(width) => {
+                usage.record(FrameOp::Pop, width);
+                continue;
+            }
+        };
+
+        match node.inner() {
+            Inner::Iden | Inner::Unit | Inner::Witness(_) | Inner::Word(_) => {}
+            Inner::Fail(_) => {
+                usage.failed = true;
+                break;
+            }
+            Inner::Jet(_) => match trace.next() {
+                Some(ExecEvent::Jet { success: true }) => {}
+                Some(ExecEvent::Jet { success: false }) => {
+                    usage.failed = true;
+                    break;
+                }
+                _ => anyhow::bail!(
+                    "Execution trace diverged from the program at jet {}",
+                    node.ihr()
+                ),
+            },
+            Inner::InjL(child) | Inner::InjR(child) | Inner::Take(child) | Inner::Drop(child) => {
+                stack.push(Task::Exec(child));
+            }
+            Inner::Comp(left, right) => {
+                let width = left.arrow().target.bit_width();
+                usage.record(FrameOp::Push, width);
+                stack.push(Task::Pop(width));
+                stack.push(Task::Exec(right));
+                stack.push(Task::Exec(left));
+            }
+            Inner::Pair(left, right) => {
+                stack.push(Task::Exec(right));
+                stack.push(Task::Exec(left));
+            }
+            Inner::Case(..) | Inner::AssertL(..) | Inner::AssertR(..) => {
+                let right = match trace.next() {
+                    Some(ExecEvent::Branch { ihr, right }) if *ihr == node.ihr() => *right,
+                    // The execution reached a pruned branch
+                    None => {
+                        usage.failed = true;
+                        break;
+                    }
+                    _ => anyhow::bail!(
+                        "Execution trace diverged from the program at case node {}",
+                        node.ihr()
+                    ),
+                };
+                let child = match (node.inner(), right) {
+                    (Inner::Case(left, _), false) | (Inner::AssertL(left, _), false) => left,
+                    (Inner::Case(_, right), true) | (Inner::AssertR(_, right), true) => right,
+                    _ => anyhow::bail!("Execution trace took the pruned branch of {}", node.ihr()),
+                };
+                stack.push(Task::Exec(child));
+            }
+            Inner::Disconnect(left, right) => {
+                // Frame holding the CMR of the right branch and the input,
+                // followed by the frame for the output of the left branch.
+                // Both are dropped after the right branch.
+                let outer = left.arrow().source.bit_width();
+                let inner = left.arrow().target.bit_width();
+                usage.record(FrameOp::Push, outer);
+                usage.record(FrameOp::Push, inner);
+                stack.push(Task::Pop(outer));
+                stack.push(Task::Pop(inner));
+                stack.push(Task::Exec(right));
+                stack.push(Task::Exec(left));
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        trace.next().is_none(),
+        "Execution trace continues after the end of the program"
+    );
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::Tracker;
+    use simfony::{dummy_env, Arguments, CompiledProgram, WitnessValues};
+    use simplicity::BitMachine;
+
+    const PROGRAM: &str = r#"fn main() {
+    let x: u32 = witness::X;
+    let (carry, sum): (bool, u32) = jet::add_32(x, 1);
+    assert!(jet::eq_32(sum, 5));
+}
+"#;
+
+    const BRANCHES: &str = r#"fn main() {
+    match witness::PATH {
+        Left(x: u32) => assert!(jet::eq_32(x, 1)),
+        Right(y: (u64, u64)) => {
+            let (a, b): (u64, u64) = y;
+            let (carry, sum): (bool, u64) = jet::add_64(a, b);
+            assert!(jet::eq_64(sum, 3));
+        }
+    }
+}
+"#;
+
+    /// Execute a program and replay the recorded trace.
+    fn execute(text: &str, witness: &str) -> (Arc<RedeemNode<Elements>>, bool, MemoryUsage) {
+        let compiled = CompiledProgram::new(text, Arguments::default(), false).unwrap();
+        let witness: WitnessValues = serde_json::from_str(witness).unwrap();
+        let satisfied = compiled.satisfy(witness).unwrap();
+        let node = satisfied.redeem().clone();
+        let env = dummy_env::dummy();
+
+        let mut machine = BitMachine::for_program(&node).unwrap();
+        let mut tracker = Tracker::new(satisfied.debug_symbols());
+        tracker.trace = Some(Vec::new());
+        let success = machine.exec_with_tracker(&node, &env, &mut tracker).is_ok();
+        let usage = replay(&node, tracker.trace.as_ref().unwrap()).unwrap();
+        (node, success, usage)
+    }
+
+    #[test]
+    fn test_replay_matches_static_bounds() {
+        // Without branches every frame is allocated, so the bounds are reached
+        let (node, success, usage) = execute(PROGRAM, r#"{"X": {"value": "4", "type": "u32"}}"#);
+        assert!(success);
+        assert!(!usage.failed);
+        let bounds = node.bounds();
+        assert_eq!(usage.peak_cells, bounds.extra_cells);
+        assert_eq!(usage.peak_frames, bounds.extra_frames);
+
+        let last = usage.timeline.last().unwrap();
+        assert_eq!((last.frames, last.cells), (0, 0));
+        let pushes = usage.timeline.iter().filter(|e| e.op == FrameOp::Push);
+        assert_eq!(pushes.count() * 2, usage.timeline.len());
+    }
+
+    #[test]
+    fn test_replay_taken_branch() {
+        let (node, success, left) = execute(
+            BRANCHES,
+            r#"{"PATH": {"value": "Left(1)", "type": "Either<u32, (u64, u64)>"}}"#,
+        );
+        assert!(success);
+        let (_, success, right) = execute(
+            BRANCHES,
+            r#"{"PATH": {"value": "Right((1, 2))", "type": "Either<u32, (u64, u64)>"}}"#,
+        );
+        assert!(success);
+
+        let bounds = node.bounds();
+        assert!(left.peak_cells < right.peak_cells);
+        assert!(right.peak_cells <= bounds.extra_cells);
+        assert!(right.peak_frames <= bounds.extra_frames);
+    }
+
+    #[test]
+    fn test_replay_failure() {
+        let (_, success, passed) = execute(PROGRAM, r#"{"X": {"value": "4", "type": "u32"}}"#);
+        assert!(success);
+        let (node, success, failed) = execute(PROGRAM, r#"{"X": {"value": "3", "type": "u32"}}"#);
+        assert!(!success);
+
+        // The execution stops at the failed assertion, before the frames are dropped
+        assert!(failed.failed);
+        assert!(failed.timeline.len() < passed.timeline.len());
+        assert!(failed.timeline.last().unwrap().frames > 0);
+        assert!(failed.report(&node.bounds()).contains("until the failure"));
+    }
+
+    #[test]
+    fn test_replay_diverged_trace() {
+        let (node, _, _) = execute(PROGRAM, r#"{"X": {"value": "4", "type": "u32"}}"#);
+        let branch = ExecEvent::Branch {
+            ihr: node.ihr(),
+            right: false,
+        };
+        assert!(replay(&node, &[branch]).is_err());
+
+        let jets = vec![ExecEvent::Jet { success: true }; 100];
+        assert!(replay(&node, &jets).is_err());
+    }
+}
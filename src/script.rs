@@ -1,7 +1,8 @@
 use anyhow::anyhow;
+use elements::schnorr::TweakedPublicKey;
 use elements::secp256k1_zkp as secp256k1;
 use elements::{
-    taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo},
+    taproot::{ControlBlock, LeafVersion, TaprootBuilder, TaprootSpendInfo},
     Address, AddressParams, Script,
 };
 use simfony::CompiledProgram;
@@ -12,22 +13,15 @@ pub fn create_script(program: &CompiledProgram) -> anyhow::Result<Script> {
     Ok(script)
 }
 
-/// Generate a (non-confidential) P2TR address from a Simfony program and a key pair
-pub fn create_p2tr_address(
-    program: CompiledProgram,
-    x_only_public_key: secp256k1::XOnlyPublicKey,
-) -> anyhow::Result<Address> {
-    let script = create_script(&program)?;
-    let spend_info = taproot_spending_info(script, x_only_public_key)?;
-
-    let address = Address::p2tr(
+/// Generate a (non-confidential) P2TR address for a taproot tree
+pub fn spend_info_address(spend_info: &TaprootSpendInfo) -> Address {
+    Address::p2tr(
         secp256k1::SECP256K1,
         spend_info.internal_key(),
         spend_info.merkle_root(),
-        None, // TODO: use different blinding pubkey
+        None, // Blinded with `Address::to_confidential` if needed
         &AddressParams::LIQUID_TESTNET,
-    );
-    Ok(address)
+    )
 }
 
 /// Taproot leaf version for Simplicity (Simfony) programs
@@ -58,7 +52,43 @@ pub fn taproot_spending_info(
         .map_err(|e| anyhow!("Failed to add leaf to taproot builder: {}", e))?;
 
     let spend_info = builder
-        .finalize(&secp256k1::SECP256K1, public_key)
+        .finalize(secp256k1::SECP256K1, public_key)
         .map_err(|e| anyhow!("Failed to finalize taproot builder: {}", e))?;
     Ok(spend_info)
 }
+
+/// Control block for spending a Simplicity leaf of a P2TR output via the script path
+pub fn simplicity_control_block(
+    script: Script,
+    spend_info: &TaprootSpendInfo,
+) -> anyhow::Result<ControlBlock> {
+    spend_info
+        .control_block(&(script.clone(), simplicity_leaf_version()))
+        .ok_or_else(|| {
+            anyhow!(
+                "Program with CMR {} is not a leaf of the taproot tree",
+                hex::encode(script.as_bytes())
+            )
+        })
+}
+
+/// Check that a control block spending `script` commits to the P2TR output key.
+pub fn control_block_commits_to(
+    control_block: &ControlBlock,
+    script: &Script,
+    output_key: secp256k1::XOnlyPublicKey,
+) -> bool {
+    control_block.verify_taproot_commitment(
+        secp256k1::SECP256K1,
+        &TweakedPublicKey::new(output_key),
+        script,
+    )
+}
+
+/// Output key of a P2TR (segwit v1) script pubkey, `None` for other outputs.
+pub fn p2tr_output_key(script_pubkey: &Script) -> Option<secp256k1::XOnlyPublicKey> {
+    if !script_pubkey.is_v1_p2tr() {
+        return None;
+    }
+    secp256k1::XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..34]).ok()
+}
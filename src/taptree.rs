@@ -0,0 +1,302 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use elements::secp256k1_zkp as secp256k1;
+use elements::taproot::{LeafVersion, TaprootBuilder, TaprootSpendInfo};
+use elements::Script;
+use serde::{Deserialize, Serialize};
+
+/// Maximum depth of a taproot tree
+/// https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki#constructing-and-spending-taproot-outputs
+const TAPROOT_MAX_DEPTH: u8 = 128;
+
+/// Taproot tree description, loaded from a JSON file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TapTreeSpec {
+    pub leaves: Vec<LeafSpec>,
+}
+
+/// Leaf of the tree: either a SimplicityHL program or a tapscript.
+/// The position in the tree is given by a weight (likelihood of being spent) or an explicit depth.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LeafSpec {
+    /// Path to the program source, relative to the spec file
+    pub program: Option<PathBuf>,
+    /// Path to the program argument file, relative to the spec file
+    pub param: Option<PathBuf>,
+    /// Hex-encoded tapscript
+    pub tapscript: Option<String>,
+    pub weight: Option<u32>,
+    pub depth: Option<u8>,
+}
+
+/// Leaf script with its position in the tree
+#[derive(Debug, Clone)]
+pub struct Leaf {
+    /// Human-readable description of the leaf
+    pub name: String,
+    pub script: Script,
+    pub version: LeafVersion,
+    pub placement: Placement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    Weight(u32),
+    Depth(u8),
+}
+
+/// Leaf as recorded in the build metadata: what the tree commits to, independent of the spec file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeafRecord {
+    /// Hex-encoded leaf script, the CMR for SimplicityHL programs
+    pub script: String,
+    pub version: u8,
+    pub placement: Placement,
+}
+
+impl From<&Leaf> for LeafRecord {
+    fn from(leaf: &Leaf) -> Self {
+        Self {
+            script: hex::encode(leaf.script.as_bytes()),
+            version: leaf.version.as_u8(),
+            placement: leaf.placement,
+        }
+    }
+}
+
+impl LeafRecord {
+    pub fn to_leaf(&self) -> Result<Leaf> {
+        let script = hex::decode(&self.script).with_context(|| "Leaf script is not valid hex")?;
+        let version = LeafVersion::from_u8(self.version)
+            .map_err(|e| anyhow!("Invalid leaf version {}: {}", self.version, e))?;
+        Ok(Leaf {
+            name: format!("recorded leaf {}", self.script),
+            script: Script::from(script),
+            version,
+            placement: self.placement,
+        })
+    }
+}
+
+/// Load a tree spec, resolving the paths of program leaves against the directory of the spec.
+pub fn load_spec(path: &Path) -> Result<TapTreeSpec> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read taptree file: {}", path.display()))?;
+    let mut spec: TapTreeSpec = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse taptree file: {}", path.display()))?;
+    anyhow::ensure!(!spec.leaves.is_empty(), "Taptree has no leaves");
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    for leaf in &mut spec.leaves {
+        leaf.program = leaf.program.as_ref().map(|p| base_dir.join(p));
+        leaf.param = leaf.param.as_ref().map(|p| base_dir.join(p));
+    }
+    Ok(spec)
+}
+
+impl LeafSpec {
+    /// Position of the leaf, defaults to weight 1.
+    pub fn placement(&self) -> Result<Placement> {
+        match (self.weight, self.depth) {
+            (Some(_), Some(_)) => Err(anyhow!("A leaf can have either a weight or a depth")),
+            (None, Some(depth)) => Ok(Placement::Depth(depth)),
+            (weight, None) => Ok(Placement::Weight(weight.unwrap_or(1))),
+        }
+    }
+
+    /// Tapscript leaf, if this is not a program leaf.
+    pub fn tapscript_leaf(&self) -> Result<Option<Leaf>> {
+        let Some(tapscript) = &self.tapscript else {
+            anyhow::ensure!(
+                self.program.is_some(),
+                "A leaf must have either a program or a tapscript"
+            );
+            return Ok(None);
+        };
+        anyhow::ensure!(
+            self.program.is_none() && self.param.is_none(),
+            "A leaf can have either a program or a tapscript"
+        );
+        let script = hex::decode(tapscript.trim()).with_context(|| "Tapscript is not valid hex")?;
+        Ok(Some(Leaf {
+            name: format!("tapscript {}", tapscript),
+            script: Script::from(script),
+            version: LeafVersion::default(),
+            placement: self.placement()?,
+        }))
+    }
+}
+
+/// Depths of the leaves of a Huffman tree built from the weights,
+/// so that likely leaves have short control blocks.
+pub fn huffman_depths(weights: &[u32]) -> Vec<u8> {
+    let mut depths = vec![0u8; weights.len()];
+    // Leaves below each node of the tree under construction
+    let mut nodes: Vec<Vec<usize>> = (0..weights.len()).map(|i| vec![i]).collect();
+    let mut heap: BinaryHeap<_> = weights
+        .iter()
+        .enumerate()
+        .map(|(i, weight)| Reverse((u64::from(*weight), i)))
+        .collect();
+
+    while heap.len() > 1 {
+        let Reverse((weight_a, a)) = heap.pop().unwrap();
+        let Reverse((weight_b, b)) = heap.pop().unwrap();
+        let mut merged = std::mem::take(&mut nodes[a]);
+        merged.append(&mut nodes[b]);
+        for leaf in &merged {
+            depths[*leaf] = depths[*leaf].saturating_add(1);
+        }
+        nodes.push(merged);
+        heap.push(Reverse((weight_a + weight_b, nodes.len() - 1)));
+    }
+    depths
+}
+
+/// Depth of every leaf. All leaves must use the same kind of placement.
+fn leaf_depths(leaves: &[Leaf]) -> Result<Vec<u8>> {
+    let weights: Option<Vec<u32>> = leaves
+        .iter()
+        .map(|leaf| match leaf.placement {
+            Placement::Weight(weight) => Some(weight),
+            Placement::Depth(_) => None,
+        })
+        .collect();
+    let depths: Option<Vec<u8>> = leaves
+        .iter()
+        .map(|leaf| match leaf.placement {
+            Placement::Depth(depth) => Some(depth),
+            Placement::Weight(_) => None,
+        })
+        .collect();
+
+    let depths = match (weights, depths) {
+        (Some(weights), _) => huffman_depths(&weights),
+        (None, Some(depths)) => depths,
+        (None, None) => anyhow::bail!("Either all leaves have a depth or none of them"),
+    };
+    if let Some(depth) = depths.iter().find(|depth| **depth > TAPROOT_MAX_DEPTH) {
+        anyhow::bail!(
+            "Taptree depth {} exceeds the maximum of {}",
+            depth,
+            TAPROOT_MAX_DEPTH
+        );
+    }
+    Ok(depths)
+}
+
+/// Build the taproot tree of the leaves.
+pub fn spend_info(
+    leaves: &[Leaf],
+    internal_key: secp256k1::XOnlyPublicKey,
+) -> Result<TaprootSpendInfo> {
+    let depths = leaf_depths(leaves)?;
+    // Adding the deepest leaves first always yields a valid depth-first order
+    let mut order: Vec<usize> = (0..leaves.len()).collect();
+    order.sort_by_key(|i| Reverse(depths[*i]));
+
+    let mut builder = TaprootBuilder::new();
+    for i in order {
+        let leaf = &leaves[i];
+        builder = builder
+            .add_leaf_with_ver(usize::from(depths[i]), leaf.script.clone(), leaf.version)
+            .map_err(|e| anyhow!("Failed to add leaf `{}` to taproot tree: {}", leaf.name, e))?;
+    }
+    builder
+        .finalize(secp256k1::SECP256K1, internal_key)
+        .map_err(|e| {
+            anyhow!(
+                "Failed to finalize taproot tree, leaf depths must form a full binary tree: {}",
+                e
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::{simplicity_leaf_version, unspendable_key};
+
+    fn leaf(byte: u8, placement: Placement) -> Leaf {
+        Leaf {
+            name: format!("leaf {}", byte),
+            script: Script::from(vec![byte; 32]),
+            version: simplicity_leaf_version(),
+            placement,
+        }
+    }
+
+    #[test]
+    fn test_huffman_depths() {
+        assert_eq!(huffman_depths(&[1]), [0]);
+        assert_eq!(huffman_depths(&[1, 1]), [1, 1]);
+        assert_eq!(huffman_depths(&[1, 1, 2]), [2, 2, 1]);
+        assert_eq!(huffman_depths(&[8, 1, 1, 2, 4]), [1, 4, 4, 3, 2]);
+    }
+
+    #[test]
+    fn test_control_blocks() {
+        let placements = [
+            vec![
+                Placement::Weight(1),
+                Placement::Weight(5),
+                Placement::Weight(2),
+            ],
+            vec![
+                Placement::Depth(2),
+                Placement::Depth(1),
+                Placement::Depth(2),
+            ],
+        ];
+        for placements in placements {
+            let leaves: Vec<_> = placements
+                .into_iter()
+                .enumerate()
+                .map(|(i, placement)| leaf(i as u8, placement))
+                .collect();
+            let info = spend_info(&leaves, unspendable_key()).unwrap();
+            for leaf in &leaves {
+                let control_block = info
+                    .control_block(&(leaf.script.clone(), leaf.version))
+                    .unwrap();
+                assert!(control_block.verify_taproot_commitment(
+                    secp256k1::SECP256K1,
+                    &info.output_key(),
+                    &leaf.script
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaf_records() {
+        let leaves = vec![leaf(1, Placement::Weight(3)), leaf(2, Placement::Depth(1))];
+        let records: Vec<LeafRecord> = leaves.iter().map(LeafRecord::from).collect();
+        let json = serde_json::to_value(&records).unwrap();
+        assert_eq!(json[0]["placement"], serde_json::json!({"weight": 3}));
+        assert_eq!(json[1]["script"], "02".repeat(32));
+
+        let records: Vec<LeafRecord> = serde_json::from_value(json).unwrap();
+        for (record, leaf) in records.iter().zip(&leaves) {
+            let restored = record.to_leaf().unwrap();
+            assert_eq!(restored.script, leaf.script);
+            assert_eq!(restored.version, leaf.version);
+            assert_eq!(restored.placement, leaf.placement);
+        }
+    }
+
+    #[test]
+    fn test_invalid_depths() {
+        let leaves = [leaf(0, Placement::Depth(1)), leaf(1, Placement::Depth(2))];
+        assert!(spend_info(&leaves, unspendable_key()).is_err());
+        let leaves = [leaf(0, Placement::Depth(1)), leaf(1, Placement::Weight(1))];
+        assert!(spend_info(&leaves, unspendable_key()).is_err());
+    }
+}
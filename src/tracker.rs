@@ -1,4 +1,7 @@
+use std::collections::{BTreeMap, VecDeque};
+
 use itertools::{Either, Itertools};
+use regex::Regex;
 
 use simfony::debug::DebugSymbols;
 use simfony::jet::{source_type, target_type};
@@ -12,13 +15,139 @@ use simplicity::ffi::ffi::UWORD;
 use simplicity::jet::type_name::TypeName;
 use simplicity::jet::{Elements, Jet};
 use simplicity::{
-    BitIter, BitIterCloseError, Cmr, EarlyEndOfStreamError, Value as SimValue, ValueRef,
+    BitIter, BitIterCloseError, Cmr, Cost, EarlyEndOfStreamError, Ihr, Value as SimValue, ValueRef,
 };
 
+use crate::memory::ExecEvent;
+
+/// Number of bits in a C word
+const UWORD_BITS: usize = std::mem::size_of::<UWORD>() * 8;
+
+/// Number of debug values kept for failure reports
+const RECENT_DBG_VALUES: usize = 5;
+
+/// Number of tracked calls kept for reconstructing the call chain
+const RECENT_CALLS: usize = 64;
+
+/// Selects which jet calls are traced and how.
+#[derive(Debug, Default)]
+pub struct JetFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    /// Only collect per-jet statistics, do not print individual calls
+    pub summary_only: bool,
+    /// Maximum number of calls to print
+    pub limit: Option<usize>,
+}
+
+impl JetFilter {
+    /// Create a filter from include/exclude glob patterns (`*` and `?` wildcards).
+    pub fn new(include: &[String], exclude: &[String]) -> anyhow::Result<Self> {
+        let compile = |patterns: &[String]| -> anyhow::Result<Vec<Regex>> {
+            patterns.iter().map(|p| glob_to_regex(p)).collect()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            summary_only: false,
+            limit: None,
+        })
+    }
+
+    /// Check if a jet with the given name passes the filter.
+    pub fn matches(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|re| re.is_match(name)))
+            && !self.exclude.iter().any(|re| re.is_match(name))
+    }
+}
+
+/// Converts a glob pattern into an anchored regex.
+fn glob_to_regex(pattern: &str) -> anyhow::Result<Regex> {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|e| anyhow::anyhow!("Invalid jet pattern `{}`: {}", pattern, e))
+}
+
+/// Aggregated statistics of a traced jet.
+#[derive(Debug, Clone, Copy)]
+pub struct JetStats {
+    pub calls: usize,
+    pub cost: Cost,
+}
+
 pub struct Tracker<'a> {
     pub debug_symbols: &'a DebugSymbols,
     pub debug_logs: bool,
     pub jet_traces: bool,
+    pub jet_filter: JetFilter,
+    /// Number of jet calls printed so far
+    pub printed_jet_calls: usize,
+    /// Per-jet statistics of the calls that passed the filter
+    pub jet_stats: BTreeMap<String, JetStats>,
+    /// Branches and jet calls in execution order, if recorded
+    pub trace: Option<Vec<ExecEvent>>,
+    /// Debug symbol of the last fallible call (assert, unwrap, jet) that was entered
+    pub last_fallible_call: Option<Cmr>,
+    /// Last debug values, oldest first
+    pub recent_dbg_values: VecDeque<(String, String)>,
+    /// Debug symbols of the last tracked calls, oldest first
+    pub recent_calls: VecDeque<Cmr>,
+}
+
+impl<'a> Tracker<'a> {
+    pub fn new(debug_symbols: &'a DebugSymbols) -> Self {
+        Self {
+            debug_symbols,
+            debug_logs: false,
+            jet_traces: false,
+            jet_filter: JetFilter::default(),
+            printed_jet_calls: 0,
+            jet_stats: BTreeMap::new(),
+            trace: None,
+            last_fallible_call: None,
+            recent_dbg_values: VecDeque::with_capacity(RECENT_DBG_VALUES),
+            recent_calls: VecDeque::with_capacity(RECENT_CALLS),
+        }
+    }
+
+    /// Format a summary of traced jet calls, sorted by cumulative cost.
+    pub fn jet_summary(&self) -> String {
+        let total_calls: usize = self.jet_stats.values().map(|s| s.calls).sum();
+        let mut lines = vec![format!(
+            "Jet calls: {} total, {} distinct",
+            total_calls,
+            self.jet_stats.len()
+        )];
+        let width = self.jet_stats.keys().map(String::len).max().unwrap_or(0);
+        for (name, stats) in self
+            .jet_stats
+            .iter()
+            .sorted_by(|a, b| b.1.cost.cmp(&a.1.cost))
+        {
+            lines.push(format!(
+                "  {:width$}  calls: {:>6}  cost: {}",
+                name,
+                stats.calls,
+                stats.cost,
+                width = width
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn push_recent_call(&mut self, cmr: Cmr) {
+        if self.recent_calls.len() == RECENT_CALLS {
+            self.recent_calls.pop_front();
+        }
+        self.recent_calls.push_back(cmr);
+    }
 }
 
 #[derive(Debug)]
@@ -28,6 +157,8 @@ pub enum TrackerError {
     EndOfBitStream,
     #[allow(dead_code)]
     UnexpectedEndOfBitStream(BitIterCloseError),
+    #[allow(dead_code)]
+    UnexpectedPadding(usize),
     ReconstructError,
     #[allow(dead_code)]
     UnexpectedValue(SimValue),
@@ -46,46 +177,86 @@ impl From<BitIterCloseError> for TrackerError {
 }
 
 impl<'a> ExecTracker<Elements> for Tracker<'a> {
-    fn track_left(&mut self, _: simplicity::Ihr) {}
+    fn track_left(&mut self, ihr: Ihr) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(ExecEvent::Branch { ihr, right: false });
+        }
+    }
 
-    fn track_right(&mut self, _: simplicity::Ihr) {}
+    fn track_right(&mut self, ihr: Ihr) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(ExecEvent::Branch { ihr, right: true });
+        }
+    }
 
     fn track_jet_call(
         &mut self,
         jet: &Elements,
         input_buffer: &[UWORD],
         output_buffer: &[UWORD],
-        _: bool,
+        success: bool,
     ) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(ExecEvent::Jet { success });
+        }
         if !self.jet_traces {
             return;
         }
 
-        let args = parse_args(jet, input_buffer).expect("parse args");
-        let result = parse_result(jet, output_buffer).expect("parse res");
-        println!(
-            "{:?}({}) = {}",
-            jet,
-            args.iter().map(ToString::to_string).join(", "),
-            result
-        );
-    }
+        let name = jet.to_string();
+        if !self.jet_filter.matches(&name) {
+            return;
+        }
 
-    fn track_dbg_call(&mut self, cmr: &Cmr, value: simplicity::Value) {
-        if !self.debug_logs {
+        self.jet_stats
+            .entry(name)
+            .and_modify(|stats| {
+                stats.calls += 1;
+                stats.cost = stats.cost + jet.cost();
+            })
+            .or_insert(JetStats {
+                calls: 1,
+                cost: jet.cost(),
+            });
+
+        if self.jet_filter.summary_only {
             return;
         }
+        if let Some(limit) = self.jet_filter.limit {
+            if self.printed_jet_calls >= limit {
+                return;
+            }
+        }
+        self.printed_jet_calls += 1;
+        println!("{}", format_jet_call(jet, input_buffer, output_buffer));
+    }
 
+    fn track_dbg_call(&mut self, cmr: &Cmr, value: simplicity::Value) {
         if let Some(tracked_call) = self.debug_symbols.get(cmr) {
             match tracked_call.map_value(&StructuralValue::from(value)) {
+                Some(Either::Left(_)) => {
+                    self.push_recent_call(*cmr);
+                    self.last_fallible_call = Some(*cmr);
+                }
                 Some(Either::Right(debug_value)) => {
-                    println!(
-                        "\x1b[1;33mDBG: {} = {}\x1b[0m",
-                        debug_value.text(),
-                        debug_value.value()
-                    );
+                    self.push_recent_call(*cmr);
+                    if self.recent_dbg_values.len() == RECENT_DBG_VALUES {
+                        self.recent_dbg_values.pop_front();
+                    }
+                    self.recent_dbg_values.push_back((
+                        debug_value.text().to_string(),
+                        debug_value.value().to_string(),
+                    ));
+
+                    if self.debug_logs {
+                        println!(
+                            "\x1b[1;33mDBG: {} = {}\x1b[0m",
+                            debug_value.text(),
+                            debug_value.value()
+                        );
+                    }
                 }
-                _ => {}
+                None => {}
             }
         }
     }
@@ -99,7 +270,7 @@ impl<'a> ExecTracker<Elements> for Tracker<'a> {
 /// Bits are reversed.
 fn words_into_bit_iter(words: &[UWORD]) -> BitIter<std::vec::IntoIter<u8>> {
     let bytes_per_word = std::mem::size_of::<UWORD>();
-    let mut bytes = Vec::with_capacity(words.len() * bytes_per_word);
+    let mut bytes = Vec::with_capacity(std::mem::size_of_val(words));
     for word in words.iter().rev() {
         for i in 0..bytes_per_word {
             let byte: u8 = ((word >> ((bytes_per_word - i - 1) * 8)) & 0xFF) as u8;
@@ -110,10 +281,10 @@ fn words_into_bit_iter(words: &[UWORD]) -> BitIter<std::vec::IntoIter<u8>> {
 }
 
 /// Converts an aliased type to a resolved type.
+/// Jet signatures only use builtin aliases such as `Ctx8` or `Outpoint`.
 fn resolve_type(aliased_type: &AliasedType) -> Result<ResolvedType, TrackerError> {
-    let get_alias = |_: &AliasName| -> Option<ResolvedType> { None };
     aliased_type
-        .resolve(get_alias)
+        .resolve_builtin()
         .map_err(TrackerError::UnexpectedAlias)
 }
 
@@ -138,15 +309,70 @@ fn collect_args(
 }
 
 /// Parses a SimValue from an array of words.
+///
+/// The buffer is laid out like a C read frame: the value occupies the trailing bits,
+/// preceded by padding up to the word boundary which must be all zeros.
 fn parse_sim_value(words: &[UWORD], type_name: TypeName) -> Result<SimValue, TrackerError> {
     let sim_type = type_name.to_final();
     let mut bit_iter = words_into_bit_iter(words);
+
+    let padding = (words.len() * UWORD_BITS).saturating_sub(sim_type.bit_width());
+    for consumed in 0..padding {
+        if bit_iter.next() == Some(true) {
+            return Err(TrackerError::UnexpectedPadding(consumed));
+        }
+    }
     let sim_value = SimValue::from_padded_bits(&mut bit_iter, &sim_type)?;
-    // TODO(m-kus): this call fails
-    //bit_iter.close()?;
     Ok(sim_value)
 }
 
+/// Formats the bits of a value as hex, used when the value cannot be reconstructed.
+fn format_raw_bits(words: &[UWORD], type_name: TypeName, simf_type: &str) -> String {
+    let bit_width = type_name.to_final().bit_width();
+    let padding = (words.len() * UWORD_BITS).saturating_sub(bit_width);
+    let bits: Vec<bool> = words_into_bit_iter(words).skip(padding).collect();
+    let bytes: Vec<u8> = bits
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, bit)| byte | ((*bit as u8) << (7 - i)))
+        })
+        .collect();
+    format!(
+        "0x{} ({} bits): {}",
+        hex::encode(bytes),
+        bit_width,
+        simf_type
+    )
+}
+
+/// Formats a jet call with its arguments and result.
+/// Never fails: values that cannot be decoded are printed as raw bits.
+fn format_jet_call(jet: &Elements, input_buffer: &[UWORD], output_buffer: &[UWORD]) -> String {
+    let args = match parse_args(jet, input_buffer) {
+        Ok(args) => args.iter().map(ToString::to_string).join(", "),
+        Err(_) => {
+            let simf_types = source_type(*jet);
+            let simf_type = match simf_types.len() {
+                1 => simf_types[0].to_string(),
+                _ => format!("({})", simf_types.iter().join(", ")),
+            };
+            format_raw_bits(input_buffer, jet.source_ty(), &simf_type)
+        }
+    };
+    let result = match parse_result(jet, output_buffer) {
+        Ok(result) => result.to_string(),
+        Err(_) => format_raw_bits(
+            output_buffer,
+            jet.target_ty(),
+            &target_type(*jet).to_string(),
+        ),
+    };
+    format!("{}({}) = {}", jet, args, result)
+}
+
 /// Parses a Simfony value from a Simplicity value.
 fn parse_simf_value(
     sim_value: SimValue,
@@ -161,7 +387,7 @@ fn parse_simf_value(
 /// Parses the arguments of a jet call.
 fn parse_args(jet: &Elements, words: &[UWORD]) -> Result<Vec<Value>, TrackerError> {
     let simf_types = source_type(*jet);
-    if simf_types.len() == 0 {
+    if simf_types.is_empty() {
         return Ok(vec![]);
     }
 
@@ -182,3 +408,262 @@ fn parse_result(jet: &Elements, words: &[UWORD]) -> Result<Value, TrackerError>
     let sim_value = parse_sim_value(words, jet.target_ty())?;
     parse_simf_value(sim_value, &simf_type)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simfony::parse::ParseFromStr;
+    use simfony::{dummy_env, Arguments, CompiledProgram, WitnessValues};
+    use simplicity::BitMachine;
+
+    /// Allocates a word buffer large enough to hold a value of the given type.
+    fn buffer_for(type_name: TypeName, fill: UWORD) -> Vec<UWORD> {
+        let bit_width = type_name.to_final().bit_width();
+        vec![fill; bit_width.div_ceil(UWORD_BITS)]
+    }
+
+    #[test]
+    fn test_decode_all_jets() {
+        let mut failures = Vec::new();
+        for jet in Elements::ALL {
+            let input = buffer_for(jet.source_ty(), 0);
+            let output = buffer_for(jet.target_ty(), 0);
+            if let Err(e) = parse_args(&jet, &input) {
+                failures.push(format!("{:?} args: {:?}", jet, e));
+            }
+            if let Err(e) = parse_result(&jet, &output) {
+                failures.push(format!("{:?} result: {:?}", jet, e));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_format_never_panics() {
+        for jet in Elements::ALL {
+            for fill in [0, UWORD::MAX] {
+                let input = buffer_for(jet.source_ty(), fill);
+                let output = buffer_for(jet.target_ty(), fill);
+                let line = format_jet_call(&jet, &input, &output);
+                assert!(line.starts_with(&format!("{}(", jet)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_unexpected_padding() {
+        // u1 occupies the least significant bit of a single word
+        let words = [1 << (UWORD_BITS - 1)];
+        assert!(matches!(
+            parse_sim_value(&words, Elements::Verify.source_ty()),
+            Err(TrackerError::UnexpectedPadding(_))
+        ));
+    }
+
+    /// Binary digits of `value` with the given width.
+    fn bits(value: u64, width: usize) -> String {
+        format!("{:0width$b}", value, width = width)
+    }
+
+    /// Word buffer holding `bits` like a C read frame: zero padding, followed by the bits.
+    fn words_from_bits(bits: &str) -> Vec<UWORD> {
+        let mut words = vec![0 as UWORD; bits.len().div_ceil(UWORD_BITS)];
+        let len = words.len();
+        let padding = len * UWORD_BITS - bits.len();
+        for (i, _) in bits.chars().enumerate().filter(|(_, bit)| *bit == '1') {
+            let i = padding + i;
+            words[len - 1 - i / UWORD_BITS] |= 1 << (UWORD_BITS - 1 - i % UWORD_BITS);
+        }
+        words
+    }
+
+    /// Decode the bits as a value of the given SimplicityHL type and render it.
+    fn render(bits: &str, type_name: &'static [u8], ty: &str) -> String {
+        let sim_value = parse_sim_value(&words_from_bits(bits), TypeName(type_name)).unwrap();
+        let ty = AliasedType::parse_from_str(ty).unwrap();
+        parse_simf_value(sim_value, &ty).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_known_values() {
+        assert_eq!(render(&bits(261, 32), b"i", "u32"), "261");
+        assert_eq!(
+            render(&format!("1{}", bits(7, 32)), b"*2i", "(bool, u32)"),
+            "(true, 7)"
+        );
+        // The padding between the tag and the value of a left branch is skipped
+        assert_eq!(
+            render(
+                &format!("0{}{}", bits(0xff, 8), bits(5, 8)),
+                b"+cs",
+                "Either<u8, u16>"
+            ),
+            "Left(5)"
+        );
+        assert_eq!(
+            render(&format!("1{}", bits(256, 16)), b"+cs", "Either<u8, u16>"),
+            "Right(256)"
+        );
+    }
+
+    #[test]
+    fn test_format_known_jet_call() {
+        let input = words_from_bits(&format!("{}{}", bits(5, 32), bits(3, 32)));
+        let output = words_from_bits(&format!("0{}", bits(8, 32)));
+        assert_eq!(
+            format_jet_call(&Elements::Add32, &input, &output),
+            "add_32(5, 3) = (false, 8)"
+        );
+
+        // Padding must be zero, otherwise the raw bits are printed
+        let mut output = output;
+        output[0] |= 1 << (UWORD_BITS - 1);
+        assert_eq!(
+            format_jet_call(&Elements::Add32, &input, &output),
+            "add_32(5, 3) = 0x0000000400 (33 bits): (bool, u32)"
+        );
+    }
+
+    /// Records the formatted jet calls of an execution.
+    struct JetCalls(Vec<String>);
+
+    impl ExecTracker<Elements> for JetCalls {
+        fn track_left(&mut self, _: Ihr) {}
+
+        fn track_right(&mut self, _: Ihr) {}
+
+        fn track_jet_call(&mut self, jet: &Elements, input: &[UWORD], output: &[UWORD], _: bool) {
+            self.0.push(format_jet_call(jet, input, output));
+        }
+
+        fn track_dbg_call(&mut self, _: &Cmr, _: SimValue) {}
+
+        fn is_track_debug_enabled(&self) -> bool {
+            false
+        }
+    }
+
+    const ADD_PROGRAM: &str = r#"fn main() {
+    let (carry, sum): (bool, u32) = jet::add_32(witness::X, 3);
+    assert!(jet::eq_32(sum, 8));
+}
+"#;
+
+    /// Executes `ADD_PROGRAM` with the given tracker.
+    fn execute<T: ExecTracker<Elements>>(tracker: &mut T) {
+        let compiled = CompiledProgram::new(ADD_PROGRAM, Arguments::default(), false).unwrap();
+        let witness: WitnessValues =
+            serde_json::from_str(r#"{"X": {"value": "5", "type": "u32"}}"#).unwrap();
+        let satisfied = compiled.satisfy(witness).unwrap();
+        let node = satisfied.redeem();
+
+        let mut machine = BitMachine::for_program(node).unwrap();
+        machine
+            .exec_with_tracker(node, &dummy_env::dummy(), tracker)
+            .unwrap();
+    }
+
+    /// Executes `ADD_PROGRAM` with jet traces and the given filter.
+    fn traced_execution(debug_symbols: &DebugSymbols, jet_filter: JetFilter) -> Tracker<'_> {
+        let mut tracker = Tracker::new(debug_symbols);
+        tracker.jet_traces = true;
+        tracker.jet_filter = jet_filter;
+        execute(&mut tracker);
+        tracker
+    }
+
+    #[test]
+    fn test_format_executed_jet_calls() {
+        let mut calls = JetCalls(Vec::new());
+        execute(&mut calls);
+        assert_eq!(
+            calls.0,
+            [
+                "add_32(5, 3) = (false, 8)",
+                "eq_32(8, 8) = true",
+                "verify(true) = ()"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_glob_to_regex() {
+        let regex = glob_to_regex("sha_256_*").unwrap();
+        assert!(regex.is_match("sha_256_ctx_8_add_32"));
+        assert!(!regex.is_match("x_sha_256_ctx_8_init"));
+
+        let regex = glob_to_regex("eq_?").unwrap();
+        assert!(regex.is_match("eq_8"));
+        assert!(!regex.is_match("eq_32"));
+
+        // Regex syntax is matched literally
+        let regex = glob_to_regex("add.32").unwrap();
+        assert!(!regex.is_match("add_32"));
+    }
+
+    #[test]
+    fn test_jet_filter() {
+        let patterns = |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect_vec();
+        let filter = JetFilter::new(&patterns(&["*_32"]), &patterns(&["eq_*"])).unwrap();
+        assert!(filter.matches("add_32"));
+        assert!(!filter.matches("eq_32"));
+        assert!(!filter.matches("verify"));
+        assert!(JetFilter::default().matches("verify"));
+
+        // The filter matches the names that are printed
+        let debug_symbols = DebugSymbols::default();
+        let tracker = traced_execution(&debug_symbols, filter);
+        assert_eq!(tracker.jet_stats.keys().collect_vec(), ["add_32"]);
+        assert_eq!(tracker.printed_jet_calls, 1);
+    }
+
+    #[test]
+    fn test_trace_limit() {
+        let debug_symbols = DebugSymbols::default();
+        let jet_filter = JetFilter {
+            limit: Some(1),
+            ..JetFilter::default()
+        };
+        let tracker = traced_execution(&debug_symbols, jet_filter);
+        assert_eq!(tracker.printed_jet_calls, 1);
+        // Calls beyond the limit are still counted
+        assert_eq!(
+            tracker.jet_stats.keys().collect_vec(),
+            ["add_32", "eq_32", "verify"]
+        );
+
+        let jet_filter = JetFilter {
+            summary_only: true,
+            ..JetFilter::default()
+        };
+        let tracker = traced_execution(&debug_symbols, jet_filter);
+        assert_eq!(tracker.printed_jet_calls, 0);
+        assert_eq!(tracker.jet_stats.len(), 3);
+    }
+
+    #[test]
+    fn test_jet_summary() {
+        let debug_symbols = DebugSymbols::default();
+        let mut tracker = traced_execution(&debug_symbols, JetFilter::default());
+        tracker.jet_stats.get_mut("eq_32").unwrap().calls = 2;
+
+        let summary = tracker.jet_summary();
+        let lines = summary.lines().collect_vec();
+        assert_eq!(lines[0], "Jet calls: 4 total, 3 distinct");
+        assert_eq!(lines.len(), 4);
+        // Sorted by cumulative cost, names padded to the same width
+        let costs = tracker
+            .jet_stats
+            .values()
+            .map(|stats| stats.cost)
+            .sorted()
+            .rev()
+            .collect_vec();
+        for (line, cost) in lines[1..].iter().zip(costs) {
+            assert!(line.ends_with(&format!("cost: {}", cost)), "{}", line);
+        }
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("  eq_32   calls:      2  cost: ")));
+    }
+}
@@ -1,113 +1,720 @@
-use elements::secp256k1_zkp as secp256k1;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use elements::schnorr::TapTweak;
+use elements::secp256k1_zkp::{self as secp256k1, rand::rngs::OsRng, Keypair, Message, SecretKey};
+use elements::sighash::{Prevouts, SchnorrSighashType, SighashCache};
 use elements::{
-    confidential::{Asset, Nonce, Value},
+    confidential::{Asset, AssetBlindingFactor, Nonce, Value, ValueBlindingFactor},
+    encode,
     pset::PartiallySignedTransaction,
-    Address, AssetId, AssetIssuance, LockTime, OutPoint, Script, Sequence, Transaction, TxIn,
-    TxInWitness, TxOut, TxOutWitness,
+    taproot::{ControlBlock, TaprootSpendInfo},
+    Address, AssetId, AssetIssuance, BlockHash, LockTime, OutPoint, Script, Sequence, Transaction,
+    TxIn, TxInWitness, TxOut, TxOutSecrets, TxOutWitness,
+};
+use serde::Deserialize;
+use simfony::CompiledProgram;
+use simplicity::jet::elements::{ElementsEnv, ElementsUtxo};
+use simplicity::jet::Elements;
+use simplicity::{BitIter, BitMachine, RedeemNode};
+
+use crate::helpers::SpendWitness;
+use crate::script::{
+    control_block_commits_to, create_script, p2tr_output_key, simplicity_control_block,
+    spend_info_address,
 };
-use simfony::{dummy_env, CompiledProgram, WitnessValues};
-
-use crate::script::{create_script, simplicity_leaf_version, taproot_spending_info};
-
-/// Spend a transaction output using P2TR script path
-pub fn spend_script_path(
-    outpoint: OutPoint,
-    utxo: TxOut,
-    address: Address,
-    x_only_public_key: secp256k1::XOnlyPublicKey,
-    program: CompiledProgram,
-    witness_values: WitnessValues,
+
+/// Minimum relay fee rate of Liquid, in sat/vB
+pub const MIN_FEE_RATE: f64 = 0.1;
+
+/// The fee is recomputed until it covers the transaction, which usually takes two passes
+const MAX_FEE_PASSES: usize = 5;
+
+/// Signatures commit to the padding, which is found on the first pass and signed on the next
+const MAX_PADDING_PASSES: usize = 3;
+
+/// How a contract output is spent
+pub enum SpendPath {
+    /// Script path: the program leaf, satisfied with the witness
+    Script {
+        program: CompiledProgram,
+        witness: SpendWitness,
+    },
+    /// Key path: the untweaked internal key, it is tweaked with the merkle root of the tree
+    Key(Keypair),
+}
+
+/// Contract output spent by a transaction input
+pub struct SpendInput {
+    pub outpoint: OutPoint,
+    pub utxo: TxOut,
+    /// Unblinded value and asset of the output, see [`unblind_utxo`]
+    pub secrets: TxOutSecrets,
+    pub spend_info: TaprootSpendInfo,
+    pub path: SpendPath,
+}
+
+/// Spend contract outputs in a single transaction.
+///
+/// Every input is satisfied against the environment of its own index and gets its own witness
+/// stack. The fee is computed from the weight of the final transaction at `fee_rate` (sat/vB).
+pub fn spend(
+    inputs: &[SpendInput],
+    outputs: &Outputs,
+    fee_rate: f64,
 ) -> anyhow::Result<Transaction> {
-    let value = utxo
-        .value
-        .explicit()
-        .ok_or(anyhow::anyhow!("UTXO value is not explicit"))?;
-    let tx = create_transaction(outpoint, address, value, 2000);
-
-    let script = create_script(&program)?;
-    let spend_info = taproot_spending_info(script.clone(), x_only_public_key)?;
-
-    let control_block = spend_info
-        .control_block(&(script.clone(), simplicity_leaf_version()))
-        .unwrap();
-
-    let satisfied_program = program
-        .satisfy_with_env(witness_values, Some(&dummy_env::dummy()))
-        .map_err(|e| anyhow::anyhow!("Failed to satisfy program: {}", e))?;
-
-    let redeem_node = satisfied_program.redeem();
-    let bounds = redeem_node.bounds();
-    // NOTE: Script cost is proportional to consumed resources but the budget depends on the witness size
-    // https://github.com/BlockstreamResearch/rust-simplicity/blob/bef2d0318a870c3aa9f399744ac1eef7ee271726/src/analysis.rs#L43
-
-    let (program_bytes, witness_bytes) = redeem_node.encode_to_vec();
-
-    let mut final_script_witness = vec![
-        witness_bytes,
-        program_bytes,
-        script.into_bytes(),
-        control_block.serialize(),
-    ];
-    // (control[0] & TAPROOT_LEAF_MASK) == TAPROOT_LEAF_TAPSIMPLICITY)
-    assert_eq!(final_script_witness[3][0] & 0xfe, 0xbe);
-
-    if !bounds.cost.is_consensus_valid() {
-        return Err(anyhow::anyhow!(
-            "Program cost exceeded the maximum allowed cost, cost = {}",
-            bounds.cost
-        ));
+    anyhow::ensure!(!inputs.is_empty(), "No inputs to spend");
+    for (index, input) in inputs.iter().enumerate() {
+        if let SpendPath::Key(keypair) = &input.path {
+            anyhow::ensure!(
+                keypair.x_only_public_key().0 == input.spend_info.internal_key(),
+                "Input {}: key {} is not the internal key of the output ({})",
+                index,
+                keypair.x_only_public_key().0,
+                input.spend_info.internal_key()
+            );
+        }
     }
 
-    // Add padding to the script witness if budget is exceeded
-    if let Some(padding) = bounds.cost.get_padding(&final_script_witness) {
+    let utxos: Vec<ElementsUtxo> = inputs
+        .iter()
+        .map(|input| input.utxo.clone().into())
+        .collect();
+    with_fee_rate(fee_rate, |fee| {
+        let mut tx = create_transaction(inputs, outputs, fee)?;
+        // Signatures commit to the annexes of all inputs, so the witnesses are redone
+        // until no input changes its padding
+        for _ in 0..MAX_PADDING_PASSES {
+            let witnesses = inputs
+                .iter()
+                .enumerate()
+                .map(|(index, input)| {
+                    match &input.path {
+                        SpendPath::Script { program, witness } => script_path_witness(
+                            &tx,
+                            utxos.clone(),
+                            index,
+                            &input.spend_info,
+                            program,
+                            witness,
+                        ),
+                        SpendPath::Key(keypair) => key_path_witness(&tx, inputs, index, keypair),
+                    }
+                    .with_context(|| format!("Failed to spend input {}", index))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let settled = tx.input.iter().zip(&witnesses).all(|(input, witness)| {
+                witness_annex(&input.witness.script_witness) == witness_annex(witness)
+            });
+            for (input, witness) in tx.input.iter_mut().zip(witnesses) {
+                input.witness.script_witness = witness;
+            }
+            if settled {
+                // Run the programs against the final transaction, annexes included,
+                // so that outputs rejected by a covenant are reported here instead of by the network
+                for (index, input) in inputs.iter().enumerate() {
+                    if let SpendPath::Script { .. } = input.path {
+                        verify_script_spend(&tx, utxos.clone(), index)
+                            .with_context(|| format!("Failed to spend input {}", index))?;
+                    }
+                }
+                return Ok(tx);
+            }
+        }
+        Err(anyhow::anyhow!(
+            "Failed to settle the padding of the inputs after {} passes",
+            MAX_PADDING_PASSES
+        ))
+    })
+}
+
+/// Script path witness stack of an input: Simplicity witness, program, CMR, control block
+/// and padding if needed.
+///
+/// `utxos` are the outputs spent by the transaction inputs, in input order. The annexes of the
+/// other inputs are read from `tx`, signatures commit to them.
+pub fn script_path_witness(
+    tx: &Transaction,
+    utxos: Vec<ElementsUtxo>,
+    index: usize,
+    spend_info: &TaprootSpendInfo,
+    program: &CompiledProgram,
+    witness: &SpendWitness,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let script = create_script(program)?;
+    let control_block = simplicity_control_block(script.clone(), spend_info)?;
+
+    // The annex (padding) is committed to by the signature hash, so it is added to the input
+    // before signing: the first pass finds the padding, the next one signs over it
+    let mut tx = tx.clone();
+    for _ in 0..MAX_PADDING_PASSES {
+        // Prune against the transaction being built, so that the branches kept are the ones
+        // taken on chain. The annex is read from the input witness.
+        let env = spend_env(tx.clone(), utxos.clone(), index as u32, program, spend_info)?;
+        // Signatures are made over this transaction, so they are redone on every fee pass
+        let witness_values = witness.fill(&sig_all_hash(&env))?;
+        let satisfied_program = program
+            .satisfy_with_env(witness_values, Some(&env))
+            .map_err(|e| anyhow::anyhow!("Failed to satisfy program: {}", e))?;
+
+        let redeem_node = satisfied_program.redeem();
+        let bounds = redeem_node.bounds();
+        // NOTE: Script cost is proportional to consumed resources but the budget depends on the witness size
+        // https://github.com/BlockstreamResearch/rust-simplicity/blob/bef2d0318a870c3aa9f399744ac1eef7ee271726/src/analysis.rs#L43
+        if !bounds.cost.is_consensus_valid() {
+            return Err(anyhow::anyhow!(
+                "Program cost exceeded the maximum allowed cost, cost = {}",
+                bounds.cost
+            ));
+        }
+
+        let (program_bytes, witness_bytes) = redeem_node.encode_to_vec();
+
+        let mut final_script_witness = vec![
+            witness_bytes,
+            program_bytes,
+            script.clone().into_bytes(),
+            control_block.serialize(),
+        ];
+        // (control[0] & TAPROOT_LEAF_MASK) == TAPROOT_LEAF_TAPSIMPLICITY)
+        assert_eq!(final_script_witness[3][0] & 0xfe, 0xbe);
+
+        // Add padding to the script witness if budget is exceeded
         // Annex has to be removed from the stack
         // https://github.com/ElementsProject/elements/blob/9748c00c3344b815d75c4b5c251b341fb34fa80f/src/script/interpreter.cpp#L3275
-        final_script_witness.push(padding);
-    } else {
-        println!("No padding needed");
+        let padding = bounds.cost.get_padding(&final_script_witness);
+        let settled = padding.as_ref() == env.annex();
+        final_script_witness.extend(padding);
+        tx.input[index].witness.script_witness = final_script_witness;
+
+        if settled {
+            if env.annex().is_none() {
+                println!("No padding needed");
+            }
+            // Run the program against the final witness, so that outputs rejected by a covenant
+            // are reported here instead of by the network
+            verify_script_spend(&tx, utxos, index)?;
+            return Ok(std::mem::take(&mut tx.input[index].witness.script_witness));
+        }
     }
+    Err(anyhow::anyhow!(
+        "Failed to settle the padding after {} passes",
+        MAX_PADDING_PASSES
+    ))
+}
+
+/// Run the Simplicity program of a finalized input against its transaction, as the network
+/// does: the program, its witness and the annex are decoded from the input witness stack.
+///
+/// `utxos` are the outputs spent by the transaction inputs, in input order.
+pub fn verify_script_spend(
+    tx: &Transaction,
+    utxos: Vec<ElementsUtxo>,
+    index: usize,
+) -> anyhow::Result<()> {
+    check_utxos(tx, &utxos, index as u32)?;
+    let stack = &tx.input[index].witness.script_witness;
+    let spend = parse_spend_witness(stack)?;
+    let node = RedeemNode::<Elements>::decode(
+        BitIter::from(spend.program.iter().copied()),
+        BitIter::from(spend.witness.iter().copied()),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to decode Simplicity program: {}", e))?;
+    anyhow::ensure!(
+        spend.script.as_bytes() == node.cmr().as_ref(),
+        "Script {} is not the CMR of the program ({})",
+        hex::encode(spend.script.as_bytes()),
+        node.cmr()
+    );
+    anyhow::ensure!(
+        p2tr_output_key(&utxos[index].script_pubkey).is_some_and(|output_key| {
+            control_block_commits_to(&spend.control_block, &spend.script, output_key)
+        }),
+        "Control block does not commit to the spent output"
+    );
+    let cost = node.bounds().cost;
+    anyhow::ensure!(
+        cost.is_budget_valid(stack),
+        "Budget exceeded, cost = {}",
+        cost
+    );
+
+    let env = ElementsEnv::new(
+        Arc::new(tx.clone()),
+        utxos,
+        index as u32,
+        node.cmr(),
+        spend.control_block,
+        spend.annex,
+        liquid_testnet_genesis_hash(),
+    );
+    BitMachine::for_program(&node)?
+        .exec(&node, &env)
+        .map_err(|e| anyhow::anyhow!("Program fails on the spending transaction: {}", e))?;
+    Ok(())
+}
+
+/// Key path witness stack of an input: a BIP341 signature with the tweaked internal key.
+fn key_path_witness(
+    tx: &Transaction,
+    inputs: &[SpendInput],
+    index: usize,
+    keypair: &Keypair,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let prevouts: Vec<TxOut> = inputs.iter().map(|input| input.utxo.clone()).collect();
+    let sighash = SighashCache::new(tx)
+        .taproot_key_spend_signature_hash(
+            index,
+            &Prevouts::All(&prevouts),
+            SchnorrSighashType::Default,
+            liquid_testnet_genesis_hash(),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to compute sighash: {}", e))?;
+    let message = Message::from_digest_slice(sighash.as_ref())
+        .with_context(|| "Failed to construct message for signing")?;
+    let tweaked = keypair
+        .tap_tweak(secp256k1::SECP256K1, inputs[index].spend_info.merkle_root())
+        .to_inner();
+    let signature = secp256k1::SECP256K1.sign_schnorr(&message, &tweaked);
+
+    // SIGHASH_DEFAULT signatures are not suffixed with the sighash type
+    Ok(vec![signature.as_ref().to_vec()])
+}
+
+/// Fee of a transaction of the given weight at `fee_rate` (sat/vB), rounded up.
+pub fn fee_for_weight(weight: usize, fee_rate: f64) -> u64 {
+    let vsize = weight.div_ceil(4);
+    (vsize as f64 * fee_rate).ceil() as u64
+}
+
+/// Build a transaction whose fee matches its final weight at `fee_rate` (sat/vB).
+///
+/// The weight depends on the witness (program, padding) and on the blinding proofs, which are
+/// only known once the transaction is built with some fee, so `build` is called until the fee
+/// covers the weight.
+fn with_fee_rate(
+    fee_rate: f64,
+    build: impl Fn(u64) -> anyhow::Result<Transaction>,
+) -> anyhow::Result<Transaction> {
+    anyhow::ensure!(
+        fee_rate.is_finite() && fee_rate >= MIN_FEE_RATE,
+        "Fee rate must be at least {} sat/vB, got {}",
+        MIN_FEE_RATE,
+        fee_rate
+    );
+    let mut fee = 0;
+    for _ in 0..MAX_FEE_PASSES {
+        let tx = build(fee)?;
+        let required = fee_for_weight(tx.weight(), fee_rate);
+        if fee >= required {
+            println!(
+                "Fee: {} sat ({} vB at {} sat/vB)",
+                fee,
+                tx.weight().div_ceil(4),
+                fee_rate
+            );
+            return Ok(tx);
+        }
+        fee = required;
+    }
+    Err(anyhow::anyhow!(
+        "Failed to find a fee covering the transaction weight after {} passes",
+        MAX_FEE_PASSES
+    ))
+}
 
-    if !bounds.cost.is_budget_valid(&final_script_witness) {
-        return Err(anyhow::anyhow!("Budget exceeded, cost = {}", bounds.cost));
+/// Environment of a transaction input that spends the program leaf of a P2TR output.
+///
+/// `utxos` are the outputs spent by the transaction inputs, in input order.
+pub fn spend_env(
+    tx: Transaction,
+    utxos: Vec<ElementsUtxo>,
+    input_index: u32,
+    program: &CompiledProgram,
+    spend_info: &TaprootSpendInfo,
+) -> anyhow::Result<ElementsEnv<Arc<Transaction>>> {
+    check_utxos(&tx, &utxos, input_index)?;
+    let utxo = &utxos[input_index as usize];
+    let address = spend_info_address(spend_info);
+    anyhow::ensure!(
+        utxo.script_pubkey == address.script_pubkey(),
+        "Input {} does not spend the program output, expected script pubkey of {}",
+        input_index,
+        address
+    );
+
+    let script = create_script(program)?;
+    let control_block = simplicity_control_block(script, spend_info)?;
+    // The annex only holds padding, it is committed to by the signature hashes
+    let annex = witness_annex(&tx.input[input_index as usize].witness.script_witness).cloned();
+    Ok(ElementsEnv::new(
+        Arc::new(tx),
+        utxos,
+        input_index,
+        program.commit().cmr(),
+        control_block,
+        annex,
+        liquid_testnet_genesis_hash(),
+    ))
+}
+
+/// Check that there is one UTXO per transaction input and that `input_index` is one of them.
+fn check_utxos(tx: &Transaction, utxos: &[ElementsUtxo], input_index: u32) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        utxos.len() == tx.input.len(),
+        "Expected one UTXO per transaction input ({}), got {}",
+        tx.input.len(),
+        utxos.len()
+    );
+    anyhow::ensure!(
+        (input_index as usize) < tx.input.len(),
+        "Input index {} is out of range, transaction has {} inputs",
+        input_index,
+        tx.input.len()
+    );
+    Ok(())
+}
+
+/// Message that `jet::sig_all_hash` computes for the input spent in `env`,
+/// which is what signatures in the witness usually sign.
+pub fn sig_all_hash(env: &ElementsEnv<Arc<Transaction>>) -> Message {
+    let hash = env.c_tx_env().sighash_all();
+    Message::from_digest_slice(&hash[..]).expect("sighash is 32 bytes")
+}
+
+/// Output spent by a transaction input, as written in UTXO files
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum UtxoEntry {
+    /// Consensus-encoded `TxOut` in hex, supports confidential outputs
+    Encoded(String),
+    /// Explicit output
+    Explicit {
+        script_pubkey: String,
+        asset: String,
+        value: u64,
+    },
+}
+
+/// Load the outputs spent by a transaction from a JSON file, see [`load_txouts`].
+pub fn load_utxos(path: &Path) -> anyhow::Result<Vec<ElementsUtxo>> {
+    Ok(load_txouts(path)?
+        .into_iter()
+        .map(ElementsUtxo::from)
+        .collect())
+}
+
+/// Load the outputs spent by a transaction from a JSON file.
+///
+/// The file holds an array with one entry per transaction input, either a hex-encoded
+/// `TxOut` or an object with `script_pubkey` (hex), `asset` (asset ID) and `value` (sats).
+pub fn load_txouts(path: &Path) -> anyhow::Result<Vec<TxOut>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read UTXO file: {}", path.display()))?;
+    let entries: Vec<UtxoEntry> = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse UTXO file: {}", path.display()))?;
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let txout = match entry {
+                UtxoEntry::Encoded(txout) => {
+                    let bytes = hex::decode(txout.trim())
+                        .with_context(|| format!("UTXO {} is not valid hex", i))?;
+                    encode::deserialize::<TxOut>(&bytes)
+                        .with_context(|| format!("Failed to decode UTXO {}", i))?
+                }
+                UtxoEntry::Explicit {
+                    script_pubkey,
+                    asset,
+                    value,
+                } => TxOut {
+                    script_pubkey: Script::from(
+                        hex::decode(&script_pubkey)
+                            .with_context(|| format!("UTXO {}: invalid script pubkey", i))?,
+                    ),
+                    asset: Asset::Explicit(
+                        AssetId::from_str(&asset)
+                            .with_context(|| format!("UTXO {}: invalid asset ID", i))?,
+                    ),
+                    value: Value::Explicit(value),
+                    nonce: Nonce::Null,
+                    witness: TxOutWitness::default(),
+                },
+            };
+            Ok(txout)
+        })
+        .collect()
+}
+
+/// Genesis block hash of Liquid testnet, committed to by signature hashes
+pub fn liquid_testnet_genesis_hash() -> BlockHash {
+    BlockHash::from_str("a771da8e52ee6ad581ed1e9a99825e5b3b7992225534eaa2ae23244fe26ab1c1")
+        .expect("constant genesis hash")
+}
+
+/// First byte of the taproot annex
+/// https://github.com/bitcoin/bips/blob/master/bip-0341.mediawiki#script-validation-rules
+const ANNEX_PREFIX: u8 = 0x50;
+
+/// Annex of a witness stack: its last element if it starts with [`ANNEX_PREFIX`]
+/// and is not the only one.
+pub fn witness_annex(stack: &[Vec<u8>]) -> Option<&Vec<u8>> {
+    match stack {
+        [_, .., last] if last.first() == Some(&ANNEX_PREFIX) => Some(last),
+        _ => None,
     }
+}
 
-    Ok(finalize_transaction(tx, final_script_witness))
+/// Simplicity spend extracted from a P2TR script path witness stack
+pub struct SimplicitySpend {
+    /// Encoded witness data of the Simplicity program
+    pub witness: Vec<u8>,
+    /// Encoded Simplicity program
+    pub program: Vec<u8>,
+    /// Tapleaf script, which is the CMR of the program
+    pub script: Script,
+    pub control_block: ControlBlock,
+    /// Annex, used as padding to increase the witness budget
+    pub annex: Option<Vec<u8>>,
 }
 
-/// Create a partially filled transaction with a single input and two outputs:
-/// - One is P2TR, locked by our program
-/// - The other is a fee output
-/// Both outputs are not confidential
-fn create_transaction(outpoint: OutPoint, address: Address, value: u64, fee: u64) -> Transaction {
-    Transaction {
+/// Split a witness stack into its Simplicity components.
+/// This is the inverse of the layout built by [`spend`].
+pub fn parse_spend_witness(stack: &[Vec<u8>]) -> anyhow::Result<SimplicitySpend> {
+    let annex = witness_annex(stack).cloned();
+    let stack = &stack[..stack.len() - usize::from(annex.is_some())];
+
+    let [witness, program, script, control_block] = stack else {
+        return Err(anyhow::anyhow!(
+            "Expected 4 witness elements (witness, program, script, control block), got {}",
+            stack.len()
+        ));
+    };
+
+    let control_block = ControlBlock::from_slice(control_block)
+        .map_err(|e| anyhow::anyhow!("Invalid control block: {}", e))?;
+
+    Ok(SimplicitySpend {
+        witness: witness.clone(),
+        program: program.clone(),
+        script: Script::from(script.clone()),
+        control_block,
+        annex,
+    })
+}
+
+/// Create a transaction spending the inputs, with:
+/// - The fixed outputs
+/// - One change output per asset that is left, sent to the change address
+/// - A fee output
+///
+/// Outputs are blinded if their address is confidential, the fee output is always explicit.
+fn create_transaction(
+    inputs: &[SpendInput],
+    outputs: &Outputs,
+    fee: u64,
+) -> anyhow::Result<Transaction> {
+    // Value left to distribute, per asset
+    let mut left = BTreeMap::new();
+    for input in inputs {
+        let total: &mut u64 = left.entry(input.secrets.asset).or_default();
+        *total = total
+            .checked_add(input.secrets.value)
+            .ok_or_else(|| anyhow::anyhow!("Input value overflow"))?;
+    }
+    for output in &outputs.outputs {
+        let available = left.get(&output.asset).copied().unwrap_or_default();
+        let rest = available.checked_sub(output.amount).ok_or_else(|| {
+            anyhow::anyhow!(
+                "Outputs spend more of asset {} than the inputs hold ({})",
+                output.asset,
+                available
+            )
+        })?;
+        left.insert(output.asset, rest);
+    }
+    let available = left.get(&tlbtc_asset_id()).copied().unwrap_or_default();
+    let rest = available.checked_sub(fee).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Value left after the outputs ({}) is lower than the fee {}",
+            available,
+            fee
+        )
+    })?;
+    left.insert(tlbtc_asset_id(), rest);
+
+    let change = left
+        .into_iter()
+        .filter(|(_, amount)| *amount > 0)
+        .map(|(asset, amount)| OutputSpec {
+            address: outputs.change.clone(),
+            amount,
+            asset,
+        });
+    let payments: Vec<OutputSpec> = outputs.outputs.iter().cloned().chain(change).collect();
+
+    let mut output: Vec<TxOut> = payments
+        .iter()
+        .map(|payment| TxOut {
+            value: Value::Explicit(payment.amount),
+            script_pubkey: payment.address.script_pubkey(),
+            asset: Asset::Explicit(payment.asset),
+            nonce: Nonce::Null,
+            witness: TxOutWitness::default(),
+        })
+        .collect();
+    output.push(TxOut::new_fee(fee, tlbtc_asset_id()));
+    let tx = Transaction {
         version: 2,
-        lock_time: LockTime::ZERO.into(),
-        input: vec![TxIn {
-            previous_output: outpoint,
-            is_pegin: false,
-            script_sig: Script::new(),
-            sequence: Sequence::MAX,
-            asset_issuance: AssetIssuance::null(),
-            witness: TxInWitness::empty(),
-        }],
-        output: vec![
-            TxOut {
-                value: Value::Explicit(value - fee),
-                script_pubkey: address.script_pubkey(),
-                asset: Asset::Explicit(tlbtc_asset_id()),
-                nonce: Nonce::Null,
-                witness: TxOutWitness::default(),
-            },
-            TxOut::new_fee(fee, tlbtc_asset_id()),
-        ],
-    }
-}
-
-/// Add a final input witness to a partially signed transaction
-fn finalize_transaction(tx: Transaction, final_script_witness: Vec<Vec<u8>>) -> Transaction {
-    let mut partial_tx = PartiallySignedTransaction::from_tx(tx);
-    partial_tx.inputs_mut()[0].final_script_witness = Some(final_script_witness);
-    partial_tx.extract_tx().unwrap()
+        lock_time: LockTime::ZERO,
+        input: inputs
+            .iter()
+            .map(|input| TxIn {
+                previous_output: input.outpoint,
+                is_pegin: false,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                asset_issuance: AssetIssuance::null(),
+                witness: TxInWitness::empty(),
+            })
+            .collect(),
+        output,
+    };
+
+    if payments.iter().all(|p| p.address.blinding_pubkey.is_none()) {
+        // Commitments of a confidential input only balance against blinded outputs
+        anyhow::ensure!(
+            inputs
+                .iter()
+                .all(|input| input.utxo.value.explicit().is_some()),
+            "Spending a confidential UTXO requires at least one confidential output address"
+        );
+        return Ok(tx);
+    }
+    let mut pset = PartiallySignedTransaction::from_tx(tx);
+    for (pset_input, input) in pset.inputs_mut().iter_mut().zip(inputs) {
+        pset_input.witness_utxo = Some(input.utxo.clone());
+    }
+    for (output, payment) in pset.outputs_mut().iter_mut().zip(&payments) {
+        if let Some(blinding_pubkey) = payment.address.blinding_pubkey {
+            output.blinding_key = Some(elements::bitcoin::PublicKey::new(blinding_pubkey));
+            output.blinder_index = Some(0);
+        }
+    }
+    pset.blind_last(
+        &mut OsRng,
+        secp256k1::SECP256K1,
+        &inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| (index, input.secrets))
+            .collect::<HashMap<_, _>>(),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to blind outputs: {}", e))?;
+    pset.extract_tx()
+        .map_err(|e| anyhow::anyhow!("Failed to extract blinded transaction: {}", e))
+}
+
+/// Output paid by a spend
+#[derive(Debug, Clone)]
+pub struct OutputSpec {
+    pub address: Address,
+    pub amount: u64,
+    pub asset: AssetId,
+}
+
+/// Outputs of a spend: the fixed outputs, plus change sent to `change` for every asset left
+#[derive(Debug, Clone)]
+pub struct Outputs {
+    pub outputs: Vec<OutputSpec>,
+    pub change: Address,
+}
+
+impl OutputSpec {
+    fn new(address: &str, amount: u64, asset: Option<&str>) -> anyhow::Result<Self> {
+        let address = Address::from_str(address)
+            .map_err(|_| anyhow::anyhow!("Invalid address `{}`", address))?;
+        let asset = match asset {
+            Some(asset) => AssetId::from_str(asset)
+                .map_err(|_| anyhow::anyhow!("Invalid asset ID `{}`", asset))?,
+            None => tlbtc_asset_id(),
+        };
+        Ok(Self {
+            address,
+            amount,
+            asset,
+        })
+    }
+}
+
+/// Parse an `ADDRESS:AMOUNT[:ASSET]` command line output, the asset defaults to L-BTC.
+impl FromStr for OutputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, ':');
+        let (Some(address), Some(amount)) = (parts.next(), parts.next()) else {
+            return Err(format!("expected ADDRESS:AMOUNT[:ASSET], found `{}`", s));
+        };
+        let amount = amount
+            .parse()
+            .map_err(|_| format!("invalid amount `{}`, expected satoshis", amount))?;
+        Self::new(address, amount, parts.next()).map_err(|e| e.to_string())
+    }
+}
+
+/// Output as written in output files
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OutputEntry {
+    address: String,
+    amount: u64,
+    asset: Option<String>,
+}
+
+/// Load the outputs of a spend from a JSON file.
+pub fn load_outputs(path: &Path) -> anyhow::Result<Vec<OutputSpec>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read output file: {}", path.display()))?;
+    let entries: Vec<OutputEntry> = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse output file: {}", path.display()))?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            OutputSpec::new(&entry.address, entry.amount, entry.asset.as_deref())
+                .with_context(|| format!("Output {}", i))
+        })
+        .collect()
+}
+
+/// Value and asset of an output, unblinding it with the blinding key if it is confidential.
+///
+/// Explicit outputs have zero blinding factors.
+pub fn unblind_utxo(
+    utxo: &TxOut,
+    blinding_key: Option<&SecretKey>,
+) -> anyhow::Result<TxOutSecrets> {
+    if let (Some(value), Some(asset)) = (utxo.value.explicit(), utxo.asset.explicit()) {
+        return Ok(TxOutSecrets::new(
+            asset,
+            AssetBlindingFactor::zero(),
+            value,
+            ValueBlindingFactor::zero(),
+        ));
+    }
+    let blinding_key = blinding_key.ok_or_else(|| {
+        anyhow::anyhow!(
+            "UTXO is confidential, provide the blinding key of the address (--blinding-key)"
+        )
+    })?;
+    utxo.unblind(secp256k1::SECP256K1, *blinding_key)
+        .map_err(|e| anyhow::anyhow!("Failed to unblind UTXO: {}", e))
 }
 
 pub fn tlbtc_asset_id() -> AssetId {
@@ -117,3 +724,369 @@ pub fn tlbtc_asset_id() -> AssetId {
     )
     .unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::load_spend_witness;
+    use crate::interface::program_interface;
+    use crate::script::{taproot_spending_info, unspendable_key};
+    use simfony::Arguments;
+
+    const SECRET_KEY: [u8; 32] = [1; 32];
+
+    const BLINDING_KEY: [u8; 32] = [2; 32];
+
+    const CHANGE_ADDRESS: &str = "tex1pvlydvg2lkew3068jyrsw469aac9lefnq0spjc7vqnxwrqrqmxrhsaythrt";
+
+    fn outpoint(vout: u32) -> OutPoint {
+        OutPoint::new(elements::Txid::from_str(&"11".repeat(32)).unwrap(), vout)
+    }
+
+    fn explicit_output(script_pubkey: Script, value: u64, asset: AssetId) -> TxOut {
+        TxOut {
+            value: Value::Explicit(value),
+            script_pubkey,
+            asset: Asset::Explicit(asset),
+            nonce: Nonce::Null,
+            witness: TxOutWitness::default(),
+        }
+    }
+
+    /// Input spending a program that checks `checks` signatures of the transaction,
+    /// the witness signs them with `SECRET_KEY`. Every check adds to the cost of the program.
+    fn signature_input(checks: usize, vout: u32, value: u64) -> SpendInput {
+        let keypair = Keypair::from_seckey_slice(secp256k1::SECP256K1, &SECRET_KEY).unwrap();
+        let source = format!(
+            "fn main() {{\n    let pk: Pubkey = 0x{};\n    let sig: Signature = witness::SIG;\n{}}}\n",
+            keypair.x_only_public_key().0,
+            "    jet::bip_0340_verify((pk, jet::sig_all_hash()), sig);\n".repeat(checks)
+        );
+        let interface = program_interface(&source).unwrap();
+        let program = CompiledProgram::new(source, Arguments::default(), false).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("sig.key"), hex::encode(SECRET_KEY)).unwrap();
+        let witness_path = dir.path().join("witness.json");
+        std::fs::write(&witness_path, r#"{"SIG": {"key_file": "sig.key"}}"#).unwrap();
+        let witness = load_spend_witness(Some(&witness_path), &[], &interface).unwrap();
+
+        let script = create_script(&program).unwrap();
+        let spend_info = taproot_spending_info(script, unspendable_key()).unwrap();
+        let utxo = explicit_output(
+            spend_info_address(&spend_info).script_pubkey(),
+            value,
+            tlbtc_asset_id(),
+        );
+        SpendInput {
+            outpoint: outpoint(vout),
+            secrets: unblind_utxo(&utxo, None).unwrap(),
+            utxo,
+            spend_info,
+            path: SpendPath::Script { program, witness },
+        }
+    }
+
+    /// Input holding an explicit `value` of `asset`, which is spent with `SECRET_KEY`.
+    fn explicit_input(vout: u32, value: u64, asset: AssetId) -> SpendInput {
+        let keypair = Keypair::from_seckey_slice(secp256k1::SECP256K1, &SECRET_KEY).unwrap();
+        let script = Script::from(vec![0x51]);
+        let spend_info = taproot_spending_info(script, keypair.x_only_public_key().0).unwrap();
+        input_spending(
+            explicit_output(
+                spend_info_address(&spend_info).script_pubkey(),
+                value,
+                asset,
+            ),
+            vout,
+            None,
+        )
+    }
+
+    /// Key-path input spending `utxo`, unblinded with `blinding_key` if it is confidential.
+    fn input_spending(utxo: TxOut, vout: u32, blinding_key: Option<&SecretKey>) -> SpendInput {
+        let keypair = Keypair::from_seckey_slice(secp256k1::SECP256K1, &SECRET_KEY).unwrap();
+        let script = Script::from(vec![0x51]);
+        SpendInput {
+            outpoint: outpoint(vout),
+            secrets: unblind_utxo(&utxo, blinding_key).unwrap(),
+            utxo,
+            spend_info: taproot_spending_info(script, keypair.x_only_public_key().0).unwrap(),
+            path: SpendPath::Key(keypair),
+        }
+    }
+
+    fn other_asset() -> AssetId {
+        AssetId::from_slice(&[7; 32]).unwrap()
+    }
+
+    /// Change address blinded with the public key of `BLINDING_KEY`.
+    fn confidential_address() -> Address {
+        let blinding_key = SecretKey::from_slice(&BLINDING_KEY).unwrap();
+        Address {
+            blinding_pubkey: Some(blinding_key.public_key(secp256k1::SECP256K1)),
+            ..Address::from_str(CHANGE_ADDRESS).unwrap()
+        }
+    }
+
+    fn change_outputs() -> Outputs {
+        Outputs {
+            outputs: vec![],
+            change: Address::from_str(CHANGE_ADDRESS).unwrap(),
+        }
+    }
+
+    fn spent_utxos(inputs: &[SpendInput]) -> Vec<ElementsUtxo> {
+        inputs
+            .iter()
+            .map(|input| input.utxo.clone().into())
+            .collect()
+    }
+
+    #[test]
+    fn test_padded_spend_signs_annex() {
+        let inputs = [signature_input(8, 0, 100_000)];
+        let tx = spend(&inputs, &change_outputs(), MIN_FEE_RATE).unwrap();
+
+        let stack = &tx.input[0].witness.script_witness;
+        assert!(witness_annex(stack).is_some(), "the spend needs padding");
+        // The signatures in the witness are checked against the final transaction
+        verify_script_spend(&tx, spent_utxos(&inputs), 0).unwrap();
+
+        // Without the annex, the budget doesn't cover the program
+        let mut unpadded = tx.clone();
+        unpadded.input[0].witness.script_witness.pop();
+        assert!(verify_script_spend(&unpadded, spent_utxos(&inputs), 0).is_err());
+    }
+
+    #[test]
+    fn test_padded_batch_signs_all_annexes() {
+        let inputs = [
+            signature_input(8, 0, 100_000),
+            signature_input(1, 1, 50_000),
+            signature_input(12, 2, 50_000),
+        ];
+        let tx = spend(&inputs, &change_outputs(), MIN_FEE_RATE).unwrap();
+
+        let padded: Vec<bool> = tx
+            .input
+            .iter()
+            .map(|input| witness_annex(&input.witness.script_witness).is_some())
+            .collect();
+        assert_eq!(padded, [true, false, true]);
+        // Every input signs the annexes of the others
+        for index in 0..inputs.len() {
+            verify_script_spend(&tx, spent_utxos(&inputs), index).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_create_transaction_change_per_asset() {
+        let inputs = [
+            explicit_input(0, 100_000, tlbtc_asset_id()),
+            explicit_input(1, 5_000, other_asset()),
+            explicit_input(2, 3_000, other_asset()),
+        ];
+        let payee = Address::from_str(CHANGE_ADDRESS).unwrap();
+        let outputs = Outputs {
+            outputs: vec![
+                OutputSpec::new(CHANGE_ADDRESS, 30_000, None).unwrap(),
+                OutputSpec {
+                    address: payee.clone(),
+                    amount: 2_000,
+                    asset: other_asset(),
+                },
+            ],
+            change: Address::from_str(CHANGE_ADDRESS).unwrap(),
+        };
+        let tx = create_transaction(&inputs, &outputs, 500).unwrap();
+
+        let amounts: Vec<(u64, AssetId)> = tx
+            .output
+            .iter()
+            .map(|output| {
+                (
+                    output.value.explicit().unwrap(),
+                    output.asset.explicit().unwrap(),
+                )
+            })
+            .collect();
+        // The outputs in order, then the change of every asset, then the fee
+        let mut change = vec![(69_500, tlbtc_asset_id()), (6_000, other_asset())];
+        change.sort_by_key(|(_, asset)| *asset);
+        let expected: Vec<_> = [(30_000, tlbtc_asset_id()), (2_000, other_asset())]
+            .into_iter()
+            .chain(change)
+            .chain([(500, tlbtc_asset_id())])
+            .collect();
+        assert_eq!(amounts, expected);
+        assert!(tx.output[..4]
+            .iter()
+            .all(|output| output.script_pubkey == payee.script_pubkey()));
+        assert!(tx.output[4].is_fee());
+        assert_eq!(tx.input.len(), 3);
+        assert_eq!(tx.input[2].previous_output, outpoint(2));
+
+        // An asset that is spent entirely gets no change output
+        let outputs = Outputs {
+            outputs: vec![OutputSpec {
+                address: payee,
+                amount: 8_000,
+                asset: other_asset(),
+            }],
+            change: Address::from_str(CHANGE_ADDRESS).unwrap(),
+        };
+        let tx = create_transaction(&inputs, &outputs, 500).unwrap();
+        assert_eq!(tx.output.len(), 3);
+        assert_eq!(tx.output[1].value.explicit(), Some(99_500));
+        assert_eq!(tx.output[1].asset.explicit(), Some(tlbtc_asset_id()));
+    }
+
+    #[test]
+    fn test_create_transaction_underflow() {
+        let inputs = [
+            explicit_input(0, 10_000, tlbtc_asset_id()),
+            explicit_input(1, 5_000, other_asset()),
+        ];
+        let outputs = |amount: u64, asset: AssetId| Outputs {
+            outputs: vec![OutputSpec {
+                address: Address::from_str(CHANGE_ADDRESS).unwrap(),
+                amount,
+                asset,
+            }],
+            change: Address::from_str(CHANGE_ADDRESS).unwrap(),
+        };
+
+        let error = create_transaction(&inputs, &outputs(5_001, other_asset()), 100).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Outputs spend more of asset {} than the inputs hold (5000)",
+                other_asset()
+            )
+        );
+        // An asset that no input holds
+        let error = create_transaction(
+            &inputs,
+            &outputs(1, AssetId::from_slice(&[8; 32]).unwrap()),
+            100,
+        )
+        .unwrap_err();
+        assert!(error.to_string().ends_with("than the inputs hold (0)"));
+        let error =
+            create_transaction(&inputs, &outputs(9_000, tlbtc_asset_id()), 1_001).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Value left after the outputs (1000) is lower than the fee 1001"
+        );
+        // The fee is paid in L-BTC only
+        let inputs = [explicit_input(0, 10_000, other_asset())];
+        let error = create_transaction(&inputs, &change_outputs(), 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Value left after the outputs (0) is lower than the fee 1"
+        );
+        assert!(create_transaction(&inputs, &change_outputs(), 0).is_ok());
+    }
+
+    #[test]
+    fn test_create_transaction_blinds_confidential_outputs() {
+        let inputs = [explicit_input(0, 100_000, tlbtc_asset_id())];
+        let outputs = Outputs {
+            outputs: vec![OutputSpec {
+                address: confidential_address(),
+                amount: 40_000,
+                asset: tlbtc_asset_id(),
+            }],
+            change: Address::from_str(CHANGE_ADDRESS).unwrap(),
+        };
+        let tx = create_transaction(&inputs, &outputs, 1_000).unwrap();
+
+        assert_eq!(tx.output.len(), 3);
+        assert!(tx.output[0].value.is_confidential());
+        assert!(tx.output[0].asset.is_confidential());
+        let blinding_key = SecretKey::from_slice(&BLINDING_KEY).unwrap();
+        let secrets = unblind_utxo(&tx.output[0], Some(&blinding_key)).unwrap();
+        assert_eq!(secrets.value, 40_000);
+        assert_eq!(secrets.asset, tlbtc_asset_id());
+        // The change and the fee stay explicit
+        assert_eq!(tx.output[1].value.explicit(), Some(59_000));
+        assert!(tx.output[2].is_fee());
+        assert_eq!(tx.fee_in(tlbtc_asset_id()), 1_000);
+
+        // The blinded output is spent again, its commitments only balance against blinded outputs
+        let inputs = [input_spending(tx.output[0].clone(), 0, Some(&blinding_key))];
+        let error = create_transaction(&inputs, &change_outputs(), 1_000).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Spending a confidential UTXO requires at least one confidential output address"
+        );
+        let outputs = Outputs {
+            outputs: vec![],
+            change: confidential_address(),
+        };
+        let tx = create_transaction(&inputs, &outputs, 1_000).unwrap();
+        assert!(tx.output[0].value.is_confidential());
+        let secrets = unblind_utxo(&tx.output[0], Some(&blinding_key)).unwrap();
+        assert_eq!(secrets.value, 39_000);
+    }
+
+    #[test]
+    fn test_with_fee_rate_converges() {
+        let inputs = [
+            signature_input(8, 0, 100_000),
+            explicit_input(1, 50_000, tlbtc_asset_id()),
+        ];
+        let outputs = change_outputs();
+        for fee_rate in [MIN_FEE_RATE, 1.0, 2.5] {
+            let fees = std::cell::RefCell::new(Vec::new());
+            let tx = with_fee_rate(fee_rate, |fee| {
+                fees.borrow_mut().push(fee);
+                create_transaction(&inputs, &outputs, fee)
+            })
+            .unwrap();
+
+            let fee = tx.fee_in(tlbtc_asset_id());
+            assert_eq!(fee, fee_for_weight(tx.weight(), fee_rate));
+            // The first pass has no fee, the second one pays for the weight of the first
+            assert_eq!(*fees.borrow(), [0, fee]);
+        }
+    }
+
+    #[test]
+    fn test_with_fee_rate_errors() {
+        let inputs = [explicit_input(0, 100_000, tlbtc_asset_id())];
+        let build = |fee| create_transaction(&inputs, &change_outputs(), fee);
+        for fee_rate in [0.0, MIN_FEE_RATE / 2.0, f64::NAN, f64::INFINITY] {
+            let error = with_fee_rate(fee_rate, build).unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("Fee rate must be at least 0.1 sat/vB"));
+        }
+
+        // Every satoshi of fee adds a virtual byte, so the fee never covers the weight
+        let passes = std::cell::Cell::new(0);
+        let error = with_fee_rate(1.0, |fee| {
+            passes.set(passes.get() + 1);
+            let mut tx = build(fee)?;
+            tx.input[0].script_sig = Script::from(vec![0; fee as usize]);
+            Ok(tx)
+        })
+        .unwrap_err();
+        assert_eq!(passes.get(), MAX_FEE_PASSES);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Failed to find a fee covering the transaction weight after {} passes",
+                MAX_FEE_PASSES
+            )
+        );
+
+        // Errors of the transaction builder are passed on
+        let error = with_fee_rate(1.0, |fee| {
+            create_transaction(&inputs, &change_outputs(), fee + 100_000)
+        })
+        .unwrap_err();
+        assert!(error.to_string().contains("is lower than the fee"));
+    }
+}
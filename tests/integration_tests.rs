@@ -1,4 +1,5 @@
 use anyhow::{Context, Result};
+use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 
@@ -108,13 +109,50 @@ impl SimfTestRunner {
         Ok(stdout.to_string())
     }
 
+    /// Debug a simf file as the first input of a spending transaction
+    pub fn debug_tx(
+        &self,
+        source_path: &Path,
+        witness_path: &Path,
+        tx_path: &Path,
+        utxos_path: &Path,
+    ) -> Result<String> {
+        let output = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("run")
+            .arg("--logging")
+            .arg("debug")
+            .arg("--entrypoint")
+            .arg(source_path)
+            .arg("--witness")
+            .arg(witness_path)
+            .arg("--tx")
+            .arg(tx_path)
+            .arg("--utxos")
+            .arg(utxos_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| {
+                format!("Failed to execute debug command for {}", self.program_name)
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Debug failed for {}: {}", self.program_name, stderr);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
     /// Run a complete test: build, run, and debug
     pub fn test(&self, source_path: &Path, witness_path: Option<&Path>) -> Result<()> {
         println!("Testing {}...", self.program_name);
 
         // Build the program
         println!("  Building...");
-        let _program_bytes = self.build(source_path, witness_path)?;
+        self.build(source_path, witness_path)?;
         println!("  ✓ Build successful");
 
         // Run the program
@@ -137,6 +175,218 @@ impl SimfTestRunner {
         Ok(())
     }
 
+    /// Format a simf file, or check that it is formatted
+    pub fn fmt(&self, source_path: &Path, check: bool) -> Result<()> {
+        let mut cmd = Command::new("cargo");
+        cmd.arg("run")
+            .arg("--")
+            .arg("fmt")
+            .arg("--entrypoint")
+            .arg(source_path);
+
+        if check {
+            cmd.arg("--check");
+        }
+
+        let output = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| format!("Failed to execute fmt command for {}", self.program_name))?;
+
+        if !output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Fmt failed for {}: {}{}", self.program_name, stdout, stderr);
+        }
+
+        Ok(())
+    }
+
+    /// Create a project from a template and run its tests
+    pub fn new_project(&self, project_path: &Path, template: &str) -> Result<()> {
+        for args in [
+            vec!["new", "--template", template],
+            vec!["test", "--entrypoint"],
+        ] {
+            let mut cmd = Command::new("cargo");
+            cmd.arg("run").arg("--").args(&args);
+            if args[0] == "new" {
+                cmd.arg(project_path);
+            } else {
+                cmd.arg(project_path.join("src").join("main.simf"));
+            }
+
+            let output = cmd
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .output()
+                .with_context(|| {
+                    format!(
+                        "Failed to execute {} command for {}",
+                        args[0], self.program_name
+                    )
+                })?;
+
+            if !output.status.success() {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                anyhow::bail!(
+                    "{} failed for {}: {}{}",
+                    args[0],
+                    self.program_name,
+                    stdout,
+                    stderr
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Build a simf file for an output with the given internal key
+    pub fn build_with_internal_key(
+        &self,
+        source_path: &Path,
+        internal_key: &str,
+        target_dir: &Path,
+    ) -> Result<()> {
+        let output = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("build")
+            .arg("--entrypoint")
+            .arg(source_path)
+            .arg("--internal-key")
+            .arg(internal_key)
+            .arg("--target-dir")
+            .arg(target_dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| {
+                format!("Failed to execute build command for {}", self.program_name)
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Build failed for {}: {}", self.program_name, stderr);
+        }
+
+        Ok(())
+    }
+
+    /// Verify that an address commits to a simf file, with the inputs recorded in its artifact
+    pub fn verify(&self, source_path: &Path, address: &str, target_dir: &Path) -> Result<String> {
+        let output = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("verify")
+            .arg("--entrypoint")
+            .arg(source_path)
+            .arg("--address")
+            .arg(address)
+            .arg("--target-dir")
+            .arg(target_dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| {
+                format!("Failed to execute verify command for {}", self.program_name)
+            })?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!(
+                "Verify failed for {}: {}\n{}",
+                self.program_name,
+                stdout,
+                stderr
+            );
+        }
+
+        Ok(stdout)
+    }
+
+    /// Run a pset subcommand with its arguments
+    pub fn pset(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("pset")
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| format!("Failed to execute pset command for {}", self.program_name))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Pset failed for {}: {}", self.program_name, stderr);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Inspect the first input of a transaction, verifying it against the spent outputs
+    pub fn inspect_tx(&self, tx_path: &Path, utxos_path: &Path) -> Result<String> {
+        let output = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("inspect-tx")
+            .arg("--tx")
+            .arg(tx_path)
+            .arg("--utxos")
+            .arg(utxos_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| {
+                format!(
+                    "Failed to execute inspect-tx command for {}",
+                    self.program_name
+                )
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Inspect-tx failed for {}: {}", self.program_name, stderr);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Print the sig_all_hash of the program spending the first input of a transaction
+    pub fn sighash(&self, source_path: &Path, tx_path: &Path, utxos_path: &Path) -> Result<String> {
+        let output = Command::new("cargo")
+            .arg("run")
+            .arg("--")
+            .arg("sighash")
+            .arg("--entrypoint")
+            .arg(source_path)
+            .arg("--tx")
+            .arg(tx_path)
+            .arg("--utxos")
+            .arg(utxos_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| {
+                format!(
+                    "Failed to execute sighash command for {}",
+                    self.program_name
+                )
+            })?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            anyhow::bail!("Sighash failed for {}: {}", self.program_name, stderr);
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
     pub fn deposit(&self, source_path: &Path) -> Result<()> {
         let mut cmd = Command::new("cargo");
         cmd.arg("run")
@@ -179,3 +429,237 @@ fn test_sighash_none() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_debug_spending_tx() -> Result<()> {
+    let runner = SimfTestRunner::new("sighash_none");
+
+    // The spent output commits to the program without debug symbols
+    let output = runner.debug_tx(
+        Path::new("tests/data/sighash_none.simf"),
+        Path::new("tests/data/sighash_none.wit"),
+        Path::new("tests/data/sighash_none.tx"),
+        Path::new("tests/data/sighash_none.utxos.json"),
+    )?;
+    assert!(output.contains("Result: "), "{}", output);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_internal_key() -> Result<()> {
+    let runner = SimfTestRunner::new("sighash_none");
+    let source_path = PathBuf::from("tests/data/sighash_none.simf");
+    let target_dir = tempfile::tempdir()?;
+    let internal_key = "1b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f";
+
+    // The recorded internal key is used to derive the address
+    runner.build_with_internal_key(&source_path, internal_key, target_dir.path())?;
+    let output = runner.verify(
+        &source_path,
+        "tex1p48fn8tvvxejghtfzc4lk8pjdfclla7m9ear46020xhyua9wa7nqsr6wpkj",
+        target_dir.path(),
+    )?;
+    assert!(output.contains("OK: address"), "{}", output);
+
+    // The address with the unspendable key commits to the same program, but not to this build
+    let error = runner
+        .verify(
+            &source_path,
+            "tex1pvlydvg2lkew3068jyrsw469aac9lefnq0spjc7vqnxwrqrqmxrhsaythrt",
+            target_dir.path(),
+        )
+        .unwrap_err();
+    assert!(error.to_string().contains("MISMATCH"), "{}", error);
+
+    Ok(())
+}
+
+#[test]
+fn test_pset_roundtrip() -> Result<()> {
+    let runner = SimfTestRunner::new("sighash_none");
+    let temp_dir = tempfile::tempdir()?;
+    let pset_path = temp_dir.path().join("spend.pset");
+    let pset = pset_path.to_str().context("temp path is not UTF-8")?;
+    let address = "tex1pvlydvg2lkew3068jyrsw469aac9lefnq0spjc7vqnxwrqrqmxrhsaythrt";
+    let utxos = "tests/data/sighash_none.utxos.json";
+    let program = ["--entrypoint", "tests/data/sighash_none.simf"];
+
+    runner.pset(&[
+        "create",
+        "--input",
+        "0000000000000000000000000000000000000000000000000000000000000001:0",
+        "--output",
+        &format!("{}:99000", address),
+        "--fee",
+        "1000",
+        "--pset",
+        pset,
+    ])?;
+    runner.pset(&[&["update", "--pset", pset, "--utxos", utxos], &program[..]].concat())?;
+    runner.pset(
+        &[
+            &[
+                "finalize",
+                "--pset",
+                pset,
+                "--witness",
+                "tests/data/sighash_none.wit",
+            ],
+            &program[..],
+        ]
+        .concat(),
+    )?;
+    let output = runner.pset(&["extract", "--pset", pset])?;
+
+    let tx_hex = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Transaction hex: "))
+        .context("transaction is not printed")?;
+    let tx_path = temp_dir.path().join("spend.tx");
+    fs::write(&tx_path, tx_hex)?;
+    let output = runner.inspect_tx(&tx_path, Path::new(utxos))?;
+    assert!(output.contains("CMR matches script: ok"), "{}", output);
+    assert!(
+        output.contains("Control block commits to the spent output: ok"),
+        "{}",
+        output
+    );
+
+    // Outputs are explicit, confidential addresses are rejected
+    let error = runner
+        .pset(&[
+            "create",
+            "--input",
+            "0000000000000000000000000000000000000000000000000000000000000001:0",
+            "--output",
+            "tlq1pqdgr6rtyzt43mpry26v38h30vav3lqaunlz95zvz8g8dyu2pmy4tk0s5j8kw3vzh6r642rm62z5srh3q05t0hsy87k60g3mms5tu055439dn4ax585fd:1000",
+            "--fee",
+            "1000",
+            "--pset",
+            pset,
+        ])
+        .unwrap_err();
+    assert!(error.to_string().contains("is confidential"), "{}", error);
+
+    Ok(())
+}
+
+#[test]
+fn test_inspect_tx() -> Result<()> {
+    let runner = SimfTestRunner::new("sighash_none");
+    let tx_path = PathBuf::from("tests/data/sighash_none.tx");
+
+    let output = runner.inspect_tx(&tx_path, Path::new("tests/data/sighash_none.utxos.json"))?;
+    assert!(output.contains("CMR matches script: ok"), "{}", output);
+    assert!(output.contains("Simplicity leaf version: ok"), "{}", output);
+    assert!(output.contains("Witness budget: ok"), "{}", output);
+    assert!(
+        output.contains("Control block commits to the spent output: ok"),
+        "{}",
+        output
+    );
+
+    // Same spend, checked against an output paying to another key
+    let output = runner.inspect_tx(&tx_path, Path::new("tests/data/other_key.utxos.json"))?;
+    assert!(
+        output.contains("Control block commits to the spent output: MISMATCH"),
+        "{}",
+        output
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_sighash_annex() -> Result<()> {
+    let runner = SimfTestRunner::new("padded_signature");
+    let source_path = PathBuf::from("tests/data/padded_signature.simf");
+    let tx_path = PathBuf::from("tests/data/padded_signature.tx");
+
+    let output = runner.sighash(
+        &source_path,
+        &tx_path,
+        Path::new("tests/data/padded_signature.utxos.json"),
+    )?;
+    assert!(output.contains("Annex (padding): "), "{}", output);
+    assert!(!output.contains("Annex (padding): none"), "{}", output);
+    assert!(!output.contains("WARNING"), "{}", output);
+
+    // The witness holds the signature of the hash, made with the annex in place
+    let message = output
+        .lines()
+        .find_map(|line| line.strip_prefix("Sighash (sig_all_hash): "))
+        .context("sighash is not printed")?;
+    let message = elements::secp256k1_zkp::Message::from_digest_slice(&hex::decode(message)?)?;
+    let tx: elements::Transaction =
+        elements::encode::deserialize(&hex::decode(fs::read_to_string(&tx_path)?.trim())?)?;
+    let signature = elements::secp256k1_zkp::schnorr::Signature::from_slice(
+        &tx.input[0].witness.script_witness[0],
+    )?;
+    let public_key = elements::secp256k1_zkp::XOnlyPublicKey::from_slice(&hex::decode(
+        "1b84c5567b126440995d3ed5aaba0565d71e1834604819ff9c17f5e9d5dd078f",
+    )?)?;
+    elements::secp256k1_zkp::SECP256K1.verify_schnorr(&signature, &message, &public_key)?;
+
+    // Without an annex in the input witness, the hash might not be the final one
+    let output = runner.sighash(
+        Path::new("tests/data/sighash_none.simf"),
+        Path::new("tests/data/sighash_none.tx"),
+        Path::new("tests/data/sighash_none.utxos.json"),
+    )?;
+    assert!(output.contains("Annex (padding): none"), "{}", output);
+    assert!(output.contains("WARNING"), "{}", output);
+
+    Ok(())
+}
+
+#[test]
+fn test_fmt_idempotent() -> Result<()> {
+    let runner = SimfTestRunner::new("fmt");
+
+    let temp_dir = tempfile::tempdir()?;
+    let source_path = temp_dir.path().join("main.simf");
+    fs::write(
+        &source_path,
+        "// Check signature\nfn main(){let pk:Pubkey=0x98dca27744caa04828985cead41574097d1d772eec3a0ddf19349b0f8804c227;\n\n\n  let msg:u256=0x7ef747fe22fb5beacac2c6d7369cf4ff3ea96ba9382e5fd9ff09b77809f3e69c; // message\njet::bip_0340_verify((pk,msg),witness::SIG);}",
+    )?;
+
+    assert!(runner.fmt(&source_path, true).is_err());
+    runner.fmt(&source_path, false)?;
+    let formatted = fs::read_to_string(&source_path)?;
+    assert_eq!(
+        formatted,
+        "// Check signature\n\
+         fn main() {\n\
+         \x20   let pk: Pubkey = 0x98dca27744caa04828985cead41574097d1d772eec3a0ddf19349b0f8804c227;\n\
+         \n\
+         \x20   let msg: u256 = 0x7ef747fe22fb5beacac2c6d7369cf4ff3ea96ba9382e5fd9ff09b77809f3e69c; // message\n\
+         \x20   jet::bip_0340_verify((pk, msg), witness::SIG);\n\
+         }\n"
+    );
+
+    runner.fmt(&source_path, true)?;
+    runner.fmt(&source_path, false)?;
+    assert_eq!(fs::read_to_string(&source_path)?, formatted);
+
+    Ok(())
+}
+
+#[test]
+fn test_project_templates() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+
+    for template in ["p2pk", "htlc", "multisig", "timelock", "vault"] {
+        let runner = SimfTestRunner::new(template);
+        let project_path = temp_dir.path().join(template);
+        runner.new_project(&project_path, template)?;
+
+        for file in ["src/main.simf", "witness.json", "args.json", ".gitignore"] {
+            assert!(project_path.join(file).exists(), "{} is missing", file);
+        }
+        runner.fmt(&project_path.join("src").join("main.simf"), true)?;
+    }
+
+    Ok(())
+}